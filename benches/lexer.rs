@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use shunting_yard_visualizer::lexer::Lexer;
+
+/// A statement mixing every kind of token, with non-ASCII text ahead of numbers.
+const LINE: &str = "total_1 = max(10, 0x1F, y * 2.5e3) + \"θ ≤ π\" ** 1_000; // ünïcode 42\n";
+
+/// Repeats [`LINE`] up to `bytes` bytes.
+fn source(bytes: usize) -> String {
+    LINE.repeat(bytes / LINE.len() + 1)
+}
+
+/// Lexes inputs that double in size. Time per byte should stay flat.
+fn lex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lex");
+    group.sample_size(10);
+    for megabytes in [1, 2, 4, 8] {
+        let source = source(megabytes << 20);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}MB", megabytes)),
+            &source,
+            |b, source| b.iter(|| Lexer::new(source.clone()).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lex);
+criterion_main!(benches);
@@ -0,0 +1,200 @@
+use crate::token::{Span, TokenValue};
+use num_bigint::BigInt;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Integer(i64),
+    BigInteger(BigInt),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Null,
+    Identifier(String),
+    Unary {
+        op: TokenValue,
+        operand: Box<Expr>,
+    },
+    Postfix {
+        op: TokenValue,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: TokenValue,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Assign {
+        op: TokenValue,
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Member {
+        object: Box<Expr>,
+        name: String,
+    },
+    MethodCall {
+        receiver: Box<Expr>,
+        name: String,
+        args: Vec<Expr>,
+    },
+    Index {
+        object: Box<Expr>,
+        indices: Vec<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+}
+
+/// An expression together with the source span it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn children(&self) -> Vec<&Expr> {
+        match &self.kind {
+            ExprKind::Integer(_)
+            | ExprKind::BigInteger(_)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Identifier(_) => vec![],
+            ExprKind::Unary { operand, .. } | ExprKind::Postfix { operand, .. } => vec![operand],
+            ExprKind::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            ExprKind::Assign { target, value, .. } => vec![target, value],
+            ExprKind::Call { args, .. } => args.iter().collect(),
+            ExprKind::Member { object, .. } => vec![object],
+            ExprKind::MethodCall { receiver, args, .. } => {
+                std::iter::once(receiver.as_ref()).chain(args).collect()
+            }
+            ExprKind::Index { object, indices } => {
+                std::iter::once(object.as_ref()).chain(indices).collect()
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => vec![condition, then, otherwise],
+        }
+    }
+
+    /// Label of this node alone, as shown in a tree diagram.
+    pub fn label(&self) -> String {
+        match &self.kind {
+            ExprKind::Integer(i) => i.to_string(),
+            ExprKind::BigInteger(i) => i.to_string(),
+            ExprKind::Float(x) => format!("{:?}", x),
+            ExprKind::Bool(b) => b.to_string(),
+            ExprKind::String(str) => format!("{:?}", str),
+            ExprKind::Null => "null".to_string(),
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::Unary { op, .. }
+            | ExprKind::Postfix { op, .. }
+            | ExprKind::Binary { op, .. }
+            | ExprKind::Assign { op, .. } => op.to_string(),
+            ExprKind::Call { name, args } => format!("{}/{}", name, args.len()),
+            ExprKind::Member { name, .. } => format!(".{}", name),
+            ExprKind::MethodCall { name, args, .. } => format!(".{}/{}", name, args.len() + 1),
+            ExprKind::Index { indices, .. } => format!("[]/{}", indices.len() + 1),
+            ExprKind::Conditional { .. } => "?:".to_string(),
+        }
+    }
+
+    /// Renders the tree with box-drawing characters, one node per line.
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, "", "");
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, head: &str, tail: &str) {
+        out.push_str(head);
+        out.push_str(&self.label());
+        out.push('\n');
+        let children = self.children();
+        for (i, child) in children.iter().enumerate() {
+            if i + 1 == children.len() {
+                child.write_tree(out, &format!("{}└── ", tail), &format!("{}    ", tail));
+            } else {
+                child.write_tree(out, &format!("{}├── ", tail), &format!("{}│   ", tail));
+            }
+        }
+    }
+}
+
+/// Fully parenthesized infix form, so precedence decisions are visible.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Integer(_)
+            | ExprKind::BigInteger(_)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Identifier(_) => {
+                write!(f, "{}", self.label())
+            }
+            ExprKind::Unary { op, operand } => {
+                let op = match op {
+                    TokenValue::UnaryPlus => "+".to_string(),
+                    TokenValue::UnaryMinus => "-".to_string(),
+                    op => op.to_string(),
+                };
+                write!(f, "({}{})", op, operand)
+            }
+            ExprKind::Postfix { op, operand } => write!(f, "({}{})", operand, op),
+            ExprKind::Binary { op, lhs, rhs } => write!(f, "({} {} {})", lhs, op, rhs),
+            ExprKind::Assign { op, target, value } => write!(f, "({} {} {})", target, op, value),
+            ExprKind::Call { name, args } => {
+                write!(f, "{}", name)?;
+                write_list(f, "(", args, ")")
+            }
+            ExprKind::Member { object, name } => write!(f, "{}.{}", object, name),
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => {
+                write!(f, "{}.{}", receiver, name)?;
+                write_list(f, "(", args, ")")
+            }
+            ExprKind::Index { object, indices } => {
+                write!(f, "{}", object)?;
+                write_list(f, "[", indices, "]")
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => write!(f, "({} ? {} : {})", condition, then, otherwise),
+        }
+    }
+}
+
+/// Writes `exprs` separated by commas between `open` and `close`.
+fn write_list(f: &mut fmt::Formatter<'_>, open: &str, exprs: &[Expr], close: &str) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    write!(f, "{}", close)
+}
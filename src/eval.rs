@@ -0,0 +1,962 @@
+use crate::registry::{BUILTIN_CONSTANTS, BUILTIN_NAMESPACE};
+use crate::token::{Loc, Span, Token, TokenValue};
+use crate::trace::{Cursor, Replay};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Rational(BigRational),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Null,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => "integer",
+            Self::Rational(_) => "rational",
+            Self::Float(_) => "float",
+            Self::Bool(_) => "bool",
+            Self::String(_) => "string",
+            Self::Null => "null",
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Rational(r) => r.to_f64(),
+            Self::Float(x) => Some(*x),
+            Self::Bool(_) | Self::String(_) | Self::Null => None,
+        }
+    }
+
+    /// The exact value of an integer or rational.
+    fn as_rational(&self) -> Option<BigRational> {
+        match self {
+            Self::Integer(i) => Some(BigRational::from_integer(BigInt::from(*i))),
+            Self::Rational(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the value counts as true as a condition.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Self::Integer(i) => *i != 0,
+            Self::Rational(r) => !r.is_zero(),
+            Self::Float(x) => *x != 0.0,
+            Self::Bool(b) => *b,
+            Self::String(str) => !str.is_empty(),
+            Self::Null => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Rational(r) => write!(f, "{}", r),
+            Self::Float(x) => write!(f, "{:?}", x),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::String(str) => write!(f, "{:?}", str),
+            Self::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Largest integer whose factorial is computed exactly, so that `n!` stays
+/// quick to compute and print.
+const MAX_EXACT_FACTORIAL: u32 = 20_000;
+
+/// Roughly how many bits an exact product, power or shift may produce, about
+/// 300,000 decimal digits, so one expression cannot take unbounded time or memory.
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+/// Largest numerator or denominator, in bits, of a fraction that arithmetic
+/// reduces. Reducing takes a gcd, whose time grows with the square of the size.
+const MAX_FRACTION_BITS: u64 = 1 << 16;
+
+/// How the evaluator represents numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// 64-bit integers, which fail on overflow, and floats.
+    #[default]
+    Native,
+    /// Integers of any size and exact fractions, so `1/3` is a third. Floats are
+    /// used only for results that cannot be exact, such as `sqrt(2)`.
+    Exact,
+}
+
+/// An entry on the value stack: a value, the binding an assignment writes to, or
+/// the name on the right of `.`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Value(Value),
+    Target(String),
+    Member(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Value(value) => write!(f, "{}", value),
+            Self::Target(name) => write!(f, "&{}", name),
+            Self::Member(name) => write!(f, ".{}", name),
+        }
+    }
+}
+
+/// Variables visible to the evaluator. Assignments write back to it, so the same
+/// environment can be reused to carry variables from one expression to the next.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    vars: HashMap<String, Value>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalErrorKind {
+    UndefinedVariable(String),
+    UnknownFunction(String),
+    WrongArgumentCount,
+    TypeMismatch(Vec<&'static str>),
+    DivisionByZero,
+    IntegerOverflow,
+    OutOfDomain,
+    IndexOutOfRange,
+    UnsupportedOperator,
+    MalformedRpn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub token: Token,
+}
+
+impl EvalError {
+    pub fn span(&self) -> Span {
+        self.token.span()
+    }
+
+    pub fn start_loc(&self) -> Loc {
+        self.token.start_loc()
+    }
+
+    pub fn end_loc(&self) -> Loc {
+        self.token.end_loc()
+    }
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedVariable(name) => write!(f, "Undefined variable '{}' in", name),
+            Self::UnknownFunction(name) => write!(f, "Unknown function '{}' in", name),
+            Self::WrongArgumentCount => write!(f, "Wrong number of arguments to"),
+            Self::TypeMismatch(types) => {
+                write!(f, "Unsupported operand type(s) {}", types.join(", "))
+            }
+            Self::DivisionByZero => write!(f, "Division by zero in"),
+            Self::IntegerOverflow => write!(f, "Integer overflow in"),
+            Self::OutOfDomain => write!(f, "Operand out of the domain of"),
+            Self::IndexOutOfRange => write!(f, "Index out of range in"),
+            Self::UnsupportedOperator => write!(f, "Cannot evaluate"),
+            Self::MalformedRpn => write!(f, "Not enough operands for"),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = self.start_loc();
+        write!(
+            f,
+            "{} '{}' at {}:{}",
+            self.kind, self.token, loc.line, loc.col
+        )
+    }
+}
+
+impl Error for EvalError {}
+
+/// The RPN still to be evaluated and the value stack, with the stack top last.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalState {
+    pub input_queue: VecDeque<Token>,
+    pub value_stack: Vec<Operand>,
+}
+
+/// One token consumed from the RPN: the values it popped and the value it pushed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalStep {
+    pub token: Token,
+    pub operands: Vec<Operand>,
+    pub result: Option<Operand>,
+    pub state: EvalState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalTrace {
+    initial: EvalState,
+    steps: Vec<EvalStep>,
+    error: Option<EvalError>,
+}
+
+impl EvalTrace {
+    pub fn initial(&self) -> &EvalState {
+        &self.initial
+    }
+
+    pub fn steps(&self) -> &[EvalStep] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn error(&self) -> Option<&EvalError> {
+        self.error.as_ref()
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, Self> {
+        Cursor::new(self)
+    }
+}
+
+impl Replay for EvalTrace {
+    type State = EvalState;
+    type Step = EvalStep;
+
+    fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    fn state_at(&self, n: usize) -> &EvalState {
+        match n {
+            0 => &self.initial,
+            n => &self.steps[n.min(self.steps.len()) - 1].state,
+        }
+    }
+
+    fn step(&self, n: usize) -> Option<&EvalStep> {
+        self.steps.get(n)
+    }
+}
+
+/// Evaluates an RPN output queue with a value stack, recording every step.
+pub struct Evaluator {
+    arithmetic: Arithmetic,
+    input_queue: VecDeque<Token>,
+    value_stack: Vec<Operand>,
+    trace: EvalTrace,
+}
+
+impl Evaluator {
+    /// Runs the evaluation. As with the parser, a failure is recorded as the final
+    /// step of the trace rather than aborting construction.
+    pub fn new(rpn: &VecDeque<Token>, env: &mut Environment, arithmetic: Arithmetic) -> Self {
+        let mut evaluator = Self {
+            arithmetic,
+            input_queue: rpn.clone(),
+            value_stack: Vec::new(),
+            trace: EvalTrace {
+                initial: EvalState {
+                    input_queue: rpn.clone(),
+                    value_stack: Vec::new(),
+                },
+                ..EvalTrace::default()
+            },
+        };
+        if let Err(e) = evaluator.evaluate(env) {
+            evaluator.trace.error = Some(e);
+        }
+        evaluator
+    }
+
+    pub fn trace(&self) -> &EvalTrace {
+        &self.trace
+    }
+
+    pub fn error(&self) -> Option<&EvalError> {
+        self.trace.error()
+    }
+
+    /// The single value left on the stack, when evaluation succeeded.
+    pub fn result(&self) -> Option<&Value> {
+        match self.value_stack.as_slice() {
+            [Operand::Value(value)] if self.error().is_none() => Some(value),
+            _ => None,
+        }
+    }
+
+    fn evaluate(&mut self, env: &mut Environment) -> Result<(), EvalError> {
+        while let Some(t) = self.input_queue.pop_front() {
+            match t.value {
+                TokenValue::Question => {
+                    let Some(Operand::Value(condition)) = self.value_stack.pop() else {
+                        return Err(self.fail(t, Vec::new(), EvalErrorKind::MalformedRpn));
+                    };
+                    if !condition.truthy() {
+                        self.skip_branch();
+                    }
+                    self.record(t, vec![Operand::Value(condition)], None);
+                    continue;
+                }
+                TokenValue::Colon => {
+                    self.skip_branch();
+                    self.record(t, Vec::new(), None);
+                    continue;
+                }
+                _ => (),
+            }
+            let arity = match &t.value {
+                TokenValue::Identifier(_) | TokenValue::Target(_) | TokenValue::Field(_) => 0,
+                literal if literal.is_literal() => 0,
+                TokenValue::Call(_, arity)
+                | TokenValue::Method(_, arity)
+                | TokenValue::Index(arity) => *arity,
+                // Only the branch taken is left for it
+                TokenValue::Ternary => 1,
+                op if op.is_prefix_op() || op.is_postfix_op() => 1,
+                _ => 2,
+            };
+            if self.value_stack.len() < arity {
+                return Err(self.fail(t, Vec::new(), EvalErrorKind::MalformedRpn));
+            }
+            let operands = self.value_stack.split_off(self.value_stack.len() - arity);
+            match apply(&t.value, &operands, env, self.arithmetic)
+                .map(|o| normalise(o, self.arithmetic))
+            {
+                Ok(value) => {
+                    self.value_stack.push(value.clone());
+                    self.record(t, operands, Some(value));
+                }
+                Err(kind) => return Err(self.fail(t, operands, kind)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the branch of a conditional that is not taken, up to and including
+    /// its `:` for the first branch, or up to its `?:` for the second. Nested
+    /// conditionals are skipped whole.
+    fn skip_branch(&mut self) {
+        let mut depth = 0;
+        while let Some(t) = self.input_queue.front() {
+            match &t.value {
+                TokenValue::Question => depth += 1,
+                TokenValue::Ternary if depth > 0 => depth -= 1,
+                TokenValue::Ternary => break,
+                TokenValue::Colon if depth == 0 => {
+                    self.input_queue.pop_front();
+                    break;
+                }
+                _ => (),
+            }
+            self.input_queue.pop_front();
+        }
+    }
+
+    fn fail(&mut self, t: Token, operands: Vec<Operand>, kind: EvalErrorKind) -> EvalError {
+        self.record(t.clone(), operands, None);
+        EvalError { kind, token: t }
+    }
+
+    fn record(&mut self, token: Token, operands: Vec<Operand>, result: Option<Operand>) {
+        self.trace.steps.push(EvalStep {
+            token,
+            operands,
+            result,
+            state: EvalState {
+                input_queue: self.input_queue.clone(),
+                value_stack: self.value_stack.clone(),
+            },
+        });
+    }
+}
+
+fn apply(
+    op: &TokenValue,
+    operands: &[Operand],
+    env: &mut Environment,
+    arithmetic: Arithmetic,
+) -> Result<Operand, EvalErrorKind> {
+    match op {
+        TokenValue::Target(name) => return Ok(Operand::Target(name.clone())),
+        TokenValue::Field(name) => return Ok(Operand::Member(name.clone())),
+        // Members are looked up as functions of the value they belong to, so
+        // `x.abs` is `abs(x)`
+        TokenValue::Dot => {
+            let [Operand::Value(object), Operand::Member(name)] = operands else {
+                return Err(EvalErrorKind::MalformedRpn);
+            };
+            return call(name, std::slice::from_ref(object)).map(Operand::Value);
+        }
+        _ => (),
+    }
+    if op.is_assignment() {
+        let [Operand::Target(name), Operand::Value(value)] = operands else {
+            return Err(EvalErrorKind::MalformedRpn);
+        };
+        let value = match compound_base(op) {
+            Some(base) => binary(&base, &lookup(env, name)?, value)?,
+            None => value.clone(),
+        };
+        env.set(name, value.clone());
+        return Ok(Operand::Value(value));
+    }
+    if op.is_increment() {
+        let [Operand::Target(name)] = operands else {
+            return Err(EvalErrorKind::MalformedRpn);
+        };
+        let old = lookup(env, name)?;
+        let step = match op {
+            TokenValue::PostIncrement => TokenValue::Plus,
+            _ => TokenValue::Minus,
+        };
+        env.set(name, binary(&step, &old, &Value::Integer(1))?);
+        return Ok(Operand::Value(old));
+    }
+
+    let mut values = Vec::with_capacity(operands.len());
+    for operand in operands {
+        match operand {
+            Operand::Value(value) => values.push(value.clone()),
+            Operand::Target(_) | Operand::Member(_) => return Err(EvalErrorKind::MalformedRpn),
+        }
+    }
+    let value = match (op, values.as_slice()) {
+        (
+            literal @ (TokenValue::Integer(_) | TokenValue::BigInteger(_) | TokenValue::Float(_)),
+            [],
+        ) => number(literal, arithmetic),
+        (TokenValue::String(str), []) => Value::String(str.clone()),
+        (TokenValue::True, []) => Value::Bool(true),
+        (TokenValue::False, []) => Value::Bool(false),
+        (TokenValue::Null, []) => Value::Null,
+        (TokenValue::Identifier(name), []) => lookup(env, name)?,
+        (TokenValue::Call(name, _) | TokenValue::Method(name, _), args) => call(name, args)?,
+        (TokenValue::Index(_), [object, index]) => subscript(object, index)?,
+        (TokenValue::Index(_), _) => return Err(EvalErrorKind::WrongArgumentCount),
+        (TokenValue::CustomOp(..), _) => return Err(EvalErrorKind::UnsupportedOperator),
+        // The '?' and ':' before it already skipped the other branch
+        (TokenValue::Ternary, [value]) => value.clone(),
+        (op, [a]) => unary(op, a)?,
+        (op, [a, b]) => binary(op, a, b)?,
+        _ => return Err(EvalErrorKind::MalformedRpn),
+    };
+    Ok(Operand::Value(value))
+}
+
+/// Exactly, integers become fractions wherever they come from, such as a
+/// variable set on the command line, so `x / y` is never truncated.
+fn normalise(operand: Operand, arithmetic: Arithmetic) -> Operand {
+    match (operand, arithmetic) {
+        (Operand::Value(Value::Integer(i)), Arithmetic::Exact) => {
+            Operand::Value(Value::Rational(BigRational::from_integer(BigInt::from(i))))
+        }
+        (operand, _) => operand,
+    }
+}
+
+/// The value of a numeric literal. Natively, integers too large for `i64` become
+/// floats. Exactly, floats become the fraction their decimal form spells out.
+fn number(literal: &TokenValue, arithmetic: Arithmetic) -> Value {
+    match (literal, arithmetic) {
+        (TokenValue::Integer(i), Arithmetic::Native) => Value::Integer(*i),
+        (TokenValue::Integer(i), Arithmetic::Exact) => {
+            Value::Rational(BigRational::from_integer(BigInt::from(*i)))
+        }
+        (TokenValue::BigInteger(i), Arithmetic::Native) => {
+            Value::Float(i.to_f64().unwrap_or(f64::INFINITY))
+        }
+        (TokenValue::BigInteger(i), Arithmetic::Exact) => {
+            Value::Rational(BigRational::from_integer(i.clone()))
+        }
+        (TokenValue::Float(x), Arithmetic::Exact) if x.is_finite() => Value::Rational(decimal(*x)),
+        (TokenValue::Float(x), _) => Value::Float(*x),
+        _ => unreachable!("not a numeric literal: {}", literal),
+    }
+}
+
+/// The fraction with the shortest decimal expansion that reads back as `x`, so
+/// `0.1` is one tenth rather than the binary float nearest to it.
+fn decimal(x: f64) -> BigRational {
+    let scientific = format!("{:e}", x);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let fraction_digits = mantissa.split_once('.').map_or(0, |(_, f)| f.len());
+    let digits: BigInt = mantissa.replace('.', "").parse().unwrap();
+    let exponent = exponent.parse::<i32>().unwrap() - fraction_digits as i32;
+    let scale = BigRational::from_integer(BigInt::from(10)).pow(exponent);
+    BigRational::from_integer(digits) * scale
+}
+
+/// The value of a variable, falling back to the builtin constants.
+fn lookup(env: &Environment, name: &str) -> Result<Value, EvalErrorKind> {
+    if let Some(value) = env.get(name) {
+        return Ok(value.clone());
+    }
+    BUILTIN_CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| Value::Float(*value))
+        .ok_or_else(|| EvalErrorKind::UndefinedVariable(name.to_string()))
+}
+
+/// The operator a compound assignment such as `+=` applies before storing.
+fn compound_base(op: &TokenValue) -> Option<TokenValue> {
+    match op {
+        TokenValue::PlusEqual => Some(TokenValue::Plus),
+        TokenValue::MinusEqual => Some(TokenValue::Minus),
+        TokenValue::StarEqual => Some(TokenValue::Star),
+        TokenValue::SlashEqual => Some(TokenValue::Slash),
+        TokenValue::ModEqual => Some(TokenValue::Mod),
+        TokenValue::StarStarEqual => Some(TokenValue::StarStar),
+        TokenValue::BitAndEqual => Some(TokenValue::BitAnd),
+        TokenValue::BitOrEqual => Some(TokenValue::BitOr),
+        TokenValue::BitXorEqual => Some(TokenValue::BitXor),
+        TokenValue::LeftShiftEqual => Some(TokenValue::LeftShift),
+        TokenValue::RightShiftEqual => Some(TokenValue::RightShift),
+        _ => None,
+    }
+}
+
+/// Orders two numbers, exactly unless one of them is a float.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a.as_rational(), b.as_rational()) {
+        (Some(x), Some(y)) => Some(x.cmp(&y)),
+        _ => a.as_float()?.partial_cmp(&b.as_float()?),
+    }
+}
+
+fn mismatch(operands: &[&Value]) -> EvalErrorKind {
+    EvalErrorKind::TypeMismatch(operands.iter().map(|v| v.type_name()).collect())
+}
+
+fn unary(op: &TokenValue, a: &Value) -> Result<Value, EvalErrorKind> {
+    match (op, a) {
+        (TokenValue::UnaryPlus, Value::Integer(_) | Value::Float(_)) => Ok(a.clone()),
+        (TokenValue::UnaryMinus, Value::Integer(i)) => i
+            .checked_neg()
+            .map(Value::Integer)
+            .ok_or(EvalErrorKind::IntegerOverflow),
+        (TokenValue::UnaryMinus, Value::Float(x)) => Ok(Value::Float(-x)),
+        (TokenValue::BitNot, Value::Integer(i)) => Ok(Value::Integer(!i)),
+        (TokenValue::LogNot, a) => Ok(Value::Bool(!a.truthy())),
+        (TokenValue::Root, a) => call("sqrt", std::slice::from_ref(a)),
+        (TokenValue::Factorial, Value::Integer(n)) if *n < 0 => Err(EvalErrorKind::OutOfDomain),
+        (TokenValue::Factorial, Value::Integer(n)) => (2..=*n)
+            .try_fold(1i64, |acc, k| acc.checked_mul(k))
+            .map(Value::Integer)
+            .ok_or(EvalErrorKind::IntegerOverflow),
+        (TokenValue::UnaryPlus, Value::Rational(_)) => Ok(a.clone()),
+        (TokenValue::UnaryMinus, Value::Rational(r)) => Ok(Value::Rational(-r)),
+        (TokenValue::BitNot, Value::Rational(r)) if r.is_integer() => {
+            Ok(Value::Rational(BigRational::from_integer(!r.to_integer())))
+        }
+        (TokenValue::Factorial, Value::Rational(r)) if r.is_integer() => {
+            if r.is_negative() {
+                return Err(EvalErrorKind::OutOfDomain);
+            }
+            let n = match r.to_integer().to_u32() {
+                Some(n) if n <= MAX_EXACT_FACTORIAL => n,
+                _ => return Err(EvalErrorKind::IntegerOverflow),
+            };
+            let product = (2..=n).fold(BigInt::from(1), |acc, k| acc * k);
+            Ok(Value::Rational(BigRational::from_integer(product)))
+        }
+        (TokenValue::Prime, _) => Err(EvalErrorKind::UnsupportedOperator),
+        (op, a) if op.is_prefix_op() || op.is_postfix_op() => Err(mismatch(&[a])),
+        _ => Err(EvalErrorKind::UnsupportedOperator),
+    }
+}
+
+fn binary(op: &TokenValue, a: &Value, b: &Value) -> Result<Value, EvalErrorKind> {
+    use TokenValue as T;
+
+    match op {
+        T::LogAnd => return Ok(Value::Bool(a.truthy() && b.truthy())),
+        T::LogOr => return Ok(Value::Bool(a.truthy() || b.truthy())),
+        T::EqualEqual => return Ok(Value::Bool(equals(a, b))),
+        T::NotEqual => return Ok(Value::Bool(!equals(a, b))),
+        _ => (),
+    }
+
+    if let (Value::Rational(_), _) | (_, Value::Rational(_)) = (a, b) {
+        if let (Some(x), Some(y)) = (a.as_rational(), b.as_rational()) {
+            return exact(op, &x, &y).ok_or_else(|| mismatch(&[a, b]))?;
+        }
+    }
+
+    if let (Value::Integer(x), Value::Integer(y)) = (a, b) {
+        let (x, y) = (*x, *y);
+        let checked = |r: Option<i64>| r.map(Value::Integer).ok_or(EvalErrorKind::IntegerOverflow);
+        return match op {
+            T::Plus => checked(x.checked_add(y)),
+            T::Minus => checked(x.checked_sub(y)),
+            T::Star => checked(x.checked_mul(y)),
+            T::Slash | T::Mod if y == 0 => Err(EvalErrorKind::DivisionByZero),
+            T::Slash => checked(x.checked_div(y)),
+            T::Mod => checked(x.checked_rem(y)),
+            T::StarStar if y >= 0 => checked(u32::try_from(y).ok().and_then(|y| x.checked_pow(y))),
+            T::StarStar => Ok(Value::Float((x as f64).powf(y as f64))),
+            T::BitAnd => Ok(Value::Integer(x & y)),
+            T::BitOr => Ok(Value::Integer(x | y)),
+            T::BitXor => Ok(Value::Integer(x ^ y)),
+            T::LeftShift | T::RightShift if !(0..64).contains(&y) => {
+                Err(EvalErrorKind::IntegerOverflow)
+            }
+            // Overflows when bits shifted out differ from the sign
+            T::LeftShift => checked(Some(x << y).filter(|r| r >> y == x)),
+            T::RightShift => Ok(Value::Integer(x >> y)),
+            T::Less => Ok(Value::Bool(x < y)),
+            T::LessEqual => Ok(Value::Bool(x <= y)),
+            T::Greater => Ok(Value::Bool(x > y)),
+            T::GreaterEqual => Ok(Value::Bool(x >= y)),
+            _ => Err(EvalErrorKind::UnsupportedOperator),
+        };
+    }
+
+    if let (Value::String(x), Value::String(y)) = (a, b) {
+        return match op {
+            T::Plus => Ok(Value::String(format!("{}{}", x, y))),
+            T::Less => Ok(Value::Bool(x < y)),
+            T::LessEqual => Ok(Value::Bool(x <= y)),
+            T::Greater => Ok(Value::Bool(x > y)),
+            T::GreaterEqual => Ok(Value::Bool(x >= y)),
+            // As for operands of different types, the types are what is wrong
+            _ => Err(mismatch(&[a, b])),
+        };
+    }
+
+    if let (Value::Bool(x), Value::Bool(y)) = (a, b) {
+        return match op {
+            T::BitAnd => Ok(Value::Bool(x & y)),
+            T::BitOr => Ok(Value::Bool(x | y)),
+            T::BitXor => Ok(Value::Bool(x ^ y)),
+            _ => Err(mismatch(&[a, b])),
+        };
+    }
+
+    let (Some(x), Some(y)) = (a.as_float(), b.as_float()) else {
+        return Err(mismatch(&[a, b]));
+    };
+    match op {
+        T::Plus => Ok(Value::Float(x + y)),
+        T::Minus => Ok(Value::Float(x - y)),
+        T::Star => Ok(Value::Float(x * y)),
+        T::Slash => Ok(Value::Float(x / y)),
+        T::Mod => Ok(Value::Float(x % y)),
+        T::StarStar => Ok(Value::Float(x.powf(y))),
+        T::Less => Ok(Value::Bool(x < y)),
+        T::LessEqual => Ok(Value::Bool(x <= y)),
+        T::Greater => Ok(Value::Bool(x > y)),
+        T::GreaterEqual => Ok(Value::Bool(x >= y)),
+        T::BitAnd | T::BitOr | T::BitXor | T::LeftShift | T::RightShift => Err(mismatch(&[a, b])),
+        _ => Err(EvalErrorKind::UnsupportedOperator),
+    }
+}
+
+/// `op` on two integers or fractions, with an exact result wherever there is
+/// one. `None` means `op` needs integer operands and at least one is a fraction.
+fn exact(
+    op: &TokenValue,
+    x: &BigRational,
+    y: &BigRational,
+) -> Option<Result<Value, EvalErrorKind>> {
+    use TokenValue as T;
+
+    let rational = |r: BigRational| Some(Ok(Value::Rational(r)));
+    let integers = match x.is_integer() && y.is_integer() {
+        true => Some((x.to_integer(), y.to_integer())),
+        false => None,
+    };
+    let overflow = Some(Err(EvalErrorKind::IntegerOverflow));
+    if matches!(op, T::Star) && bits(x) + bits(y) > MAX_EXACT_BITS {
+        return overflow;
+    }
+    // Integers need no reducing, so they skip the gcd fractions go through
+    if let Some((a, b)) = &integers {
+        match op {
+            T::Plus => return rational((a + b).into()),
+            T::Minus => return rational((a - b).into()),
+            T::Star => return rational((a * b).into()),
+            T::Mod if !b.is_zero() => return rational((a % b).into()),
+            _ => (),
+        }
+    }
+    if matches!(op, T::Plus | T::Minus | T::Star | T::Slash | T::Mod)
+        && bits(x).max(bits(y)) > MAX_FRACTION_BITS
+    {
+        return overflow;
+    }
+    match op {
+        T::Plus => rational(x + y),
+        T::Minus => rational(x - y),
+        T::Star => rational(x * y),
+        T::Slash | T::Mod if y.is_zero() => Some(Err(EvalErrorKind::DivisionByZero)),
+        T::Slash => rational(x / y),
+        T::Mod => rational(x % y),
+        T::StarStar if !y.is_integer() => Some(Ok(Value::Float(x.to_f64()?.powf(y.to_f64()?)))),
+        T::StarStar if x.is_zero() && y.is_negative() => Some(Err(EvalErrorKind::DivisionByZero)),
+        T::StarStar => {
+            // Each factor adds about as many bits as the base has beyond the first
+            let base_bits = bits(x).saturating_sub(1);
+            match y.to_integer().to_i32() {
+                Some(n) if base_bits.saturating_mul(n.unsigned_abs().into()) <= MAX_EXACT_BITS => {
+                    rational(num_traits::Pow::pow(x, n))
+                }
+                _ => Some(Err(EvalErrorKind::IntegerOverflow)),
+            }
+        }
+        T::BitAnd => integers.map(|(x, y)| Ok(Value::Rational((x & y).into()))),
+        T::BitOr => integers.map(|(x, y)| Ok(Value::Rational((x | y).into()))),
+        T::BitXor => integers.map(|(x, y)| Ok(Value::Rational((x ^ y).into()))),
+        T::LeftShift | T::RightShift => integers.map(|(x, y)| match y.to_u32() {
+            Some(n) if *op == T::LeftShift && x.bits() + u64::from(n) > MAX_EXACT_BITS => {
+                Err(EvalErrorKind::IntegerOverflow)
+            }
+            Some(n) if *op == T::LeftShift => Ok(Value::Rational((x << n).into())),
+            Some(n) => Ok(Value::Rational((x >> n).into())),
+            None => Err(EvalErrorKind::IntegerOverflow),
+        }),
+        T::Less => Some(Ok(Value::Bool(x < y))),
+        T::LessEqual => Some(Ok(Value::Bool(x <= y))),
+        T::Greater => Some(Ok(Value::Bool(x > y))),
+        T::GreaterEqual => Some(Ok(Value::Bool(x >= y))),
+        _ => Some(Err(EvalErrorKind::UnsupportedOperator)),
+    }
+}
+
+/// Size of the larger of a fraction's numerator and denominator.
+fn bits(r: &BigRational) -> u64 {
+    r.numer().bits().max(r.denom().bits())
+}
+
+fn subscript(object: &Value, index: &Value) -> Result<Value, EvalErrorKind> {
+    let position = match index {
+        Value::Integer(i) => usize::try_from(*i).ok(),
+        Value::Rational(r) if r.is_integer() => r.to_integer().to_usize(),
+        _ => return Err(mismatch(&[object, index])),
+    };
+    match object {
+        Value::String(str) => position
+            .and_then(|i| str.chars().nth(i))
+            .map(|c| Value::String(c.to_string()))
+            .ok_or(EvalErrorKind::IndexOutOfRange),
+        _ => Err(mismatch(&[object, index])),
+    }
+}
+
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::String(x), Value::String(y)) => x == y,
+        (Value::Null, Value::Null) => true,
+        _ => match (a.as_rational(), b.as_rational()) {
+            (Some(x), Some(y)) => x == y,
+            _ => match (a.as_float(), b.as_float()) {
+                (Some(x), Some(y)) => x == y,
+                _ => false,
+            },
+        },
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value, EvalErrorKind> {
+    let name = name
+        .strip_prefix(BUILTIN_NAMESPACE)
+        .and_then(|rest| rest.strip_prefix("::"))
+        .unwrap_or(name);
+    let floats = || -> Result<Vec<f64>, EvalErrorKind> {
+        args.iter()
+            .map(|a| a.as_float().ok_or_else(|| mismatch(&[a])))
+            .collect()
+    };
+    let unary_float = |f: fn(f64) -> f64| -> Result<Value, EvalErrorKind> {
+        match floats()?.as_slice() {
+            [x] => Ok(Value::Float(f(*x))),
+            _ => Err(EvalErrorKind::WrongArgumentCount),
+        }
+    };
+
+    match name {
+        "max" | "min" => {
+            let mut best: Option<&Value> = None;
+            for arg in args {
+                if arg.as_float().is_none() {
+                    return Err(mismatch(&[arg]));
+                }
+                let replace = match best.map(|b| compare(arg, b)) {
+                    None => true,
+                    Some(ordering) if name == "max" => ordering == Some(Ordering::Greater),
+                    Some(ordering) => ordering == Some(Ordering::Less),
+                };
+                if replace {
+                    best = Some(arg);
+                }
+            }
+            best.cloned().ok_or(EvalErrorKind::WrongArgumentCount)
+        }
+        "abs" => match args {
+            [Value::Integer(i)] => i
+                .checked_abs()
+                .map(Value::Integer)
+                .ok_or(EvalErrorKind::IntegerOverflow),
+            [Value::Rational(r)] => Ok(Value::Rational(r.abs())),
+            _ => unary_float(f64::abs),
+        },
+        "sqrt" => unary_float(f64::sqrt),
+        "exp" => unary_float(f64::exp),
+        "ln" => unary_float(f64::ln),
+        "sin" => unary_float(f64::sin),
+        "cos" => unary_float(f64::cos),
+        "tan" => unary_float(f64::tan),
+        "floor" => match args {
+            [Value::Rational(r)] => Ok(Value::Rational(r.floor())),
+            _ => unary_float(f64::floor),
+        },
+        "ceil" => match args {
+            [Value::Rational(r)] => Ok(Value::Rational(r.ceil())),
+            _ => unary_float(f64::ceil),
+        },
+        "round" => match args {
+            [Value::Rational(r)] => Ok(Value::Rational(r.round())),
+            _ => unary_float(f64::round),
+        },
+        "pow" => match args {
+            [x, y] => binary(&TokenValue::StarStar, x, y),
+            _ => Err(EvalErrorKind::WrongArgumentCount),
+        },
+        _ => Err(EvalErrorKind::UnknownFunction(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::operators::OperatorTable;
+    use crate::parser::Program;
+    use crate::registry::Registry;
+
+    /// Runs each statement of `source` in turn, returning the last value.
+    fn run(
+        source: &str,
+        env: &mut Environment,
+        arithmetic: Arithmetic,
+    ) -> Result<Value, EvalError> {
+        let lexer = Lexer::new(source.to_string()).unwrap();
+        let program = Program::new(
+            lexer.tokens,
+            &Registry::default(),
+            &OperatorTable::default(),
+        );
+        assert_eq!(program.error(), None);
+        let mut result = Value::Null;
+        for statement in program.statements() {
+            let evaluator = Evaluator::new(&statement.output_queue, env, arithmetic);
+            if let Some(e) = evaluator.error() {
+                return Err(e.clone());
+            }
+            result = evaluator.result().unwrap().clone();
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn only_the_branch_taken_is_evaluated() {
+        let mut env = Environment::new();
+        let result = run(
+            "x = 0; true ? (x = 1) : (x = 2); x",
+            &mut env,
+            Arithmetic::Native,
+        );
+        assert_eq!(result, Ok(Value::Integer(1)));
+
+        let result = run("x = 0; false ? 1/x : 0", &mut env, Arithmetic::Native);
+        assert_eq!(result, Ok(Value::Integer(0)));
+
+        let result = run(
+            "0 ? (1 ? 5 : 6) : (0 ? 7 : 8)",
+            &mut env,
+            Arithmetic::Native,
+        );
+        assert_eq!(result, Ok(Value::Integer(8)));
+    }
+
+    #[test]
+    fn exact_arithmetic_applies_to_bound_integers() {
+        let mut env = Environment::new();
+        env.set("x", Value::Integer(7));
+        env.set("y", Value::Integer(2));
+        let half = BigRational::new(BigInt::from(7), BigInt::from(2));
+        assert_eq!(
+            run("x / y", &mut env, Arithmetic::Exact),
+            Ok(Value::Rational(half))
+        );
+        assert_eq!(
+            run("x / y", &mut env, Arithmetic::Native),
+            Ok(Value::Integer(3))
+        );
+    }
+
+    #[test]
+    fn exact_extremes_are_compared_exactly() {
+        let mut env = Environment::new();
+        let result = run(
+            "min(1/3, 1/3 - 10**-30) == 1/3",
+            &mut env,
+            Arithmetic::Exact,
+        );
+        assert_eq!(result, Ok(Value::Bool(false)));
+        let result = run(
+            "max(10**400, 10**400 + 1) == 10**400",
+            &mut env,
+            Arithmetic::Exact,
+        );
+        assert_eq!(result, Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn undefined_variable_is_named() {
+        for (source, message) in [
+            ("x += 1", "Undefined variable 'x' in '+=' at 1:3"),
+            ("x++ + 1", "Undefined variable 'x' in '++' at 1:2"),
+        ] {
+            let error = run(source, &mut Environment::new(), Arithmetic::Native).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+}
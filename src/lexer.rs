@@ -1,28 +1,117 @@
-use crate::token::KEYWORD_TABLE;
-use crate::{Loc, Token, TokenValue};
+use crate::token::{Loc, Span, Token, TokenValue, KEYWORD_TABLE};
+use num_bigint::BigInt;
+use std::error::Error;
+use std::fmt;
+use unicode_xid::UnicodeXID;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexErrorKind {
+    UnexpectedCharacter(char),
+    InvalidEscape(char),
+    UnterminatedString,
+    MissingDigits(String),
+    MissingExponent(String),
+    MisplacedSeparator(String),
+    InvalidDigit { digit: char, radix: u32 },
+    InvalidSuffix(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn start_loc(&self) -> Loc {
+        self.span.start_loc
+    }
+
+    pub fn end_loc(&self) -> Loc {
+        self.span.end_loc
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedCharacter(c) => write!(f, "Unexpected character {:?}", c),
+            Self::InvalidEscape(c) => write!(f, "Invalid escape sequence '\\{}'", c),
+            Self::UnterminatedString => write!(f, "Unterminated string"),
+            Self::MissingDigits(literal) => write!(f, "Expected digits after {}", literal),
+            Self::MissingExponent(literal) => {
+                write!(f, "Expected exponent digits after {}", literal)
+            }
+            Self::MisplacedSeparator(literal) => {
+                write!(
+                    f,
+                    "Digit separator '_' must be followed by a digit in {}",
+                    literal
+                )
+            }
+            Self::InvalidDigit { digit, radix } => {
+                write!(f, "Invalid digit {:?} in base {} literal", digit, radix)
+            }
+            Self::InvalidSuffix(c) => write!(f, "Unexpected {:?} after number literal", c),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = self.start_loc();
+        write!(f, "{} at {}:{}", self.kind, loc.line, loc.col)
+    }
+}
+
+impl Error for LexError {}
+
+/// Options for tokenizing beyond the built-in token set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LexerConfig {
+    /// Symbol sequences such as `<=>` or `|>` to lex as [`TokenValue::Operator`].
+    /// Where one overlaps a built-in token, the longer match wins, and a symbol
+    /// wins a tie.
+    pub operators: Vec<String>,
+    /// Whether to read glyphs such as `×`, `≤` and `π` as the operators and
+    /// constants they stand for, see [`math_glyph`].
+    pub math_glyphs: bool,
+}
+
+/// Positions are byte offsets into `source`, always on a character boundary, so
+/// each step and each lexeme is a slice rather than a scan from the start.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Lexer {
     source: String,
+    config: LexerConfig,
     pub tokens: Vec<Token>,
-    start_idx: u32,
+    start_idx: usize,
     start_loc: Loc,
-    curr_idx: u32,
+    curr_idx: usize,
     curr_loc: Loc,
 }
 
 impl Lexer {
-    pub fn new(source: String) -> Self {
+    pub fn new(source: String) -> Result<Self, LexError> {
+        Self::with_config(source, LexerConfig::default())
+    }
+
+    pub fn with_config(source: String, config: LexerConfig) -> Result<Self, LexError> {
         let mut l = Self {
             source,
+            config,
             tokens: Vec::new(),
             start_idx: 0,
             start_loc: Loc { line: 1, col: 1 },
             curr_idx: 0,
             curr_loc: Loc { line: 1, col: 1 },
         };
-        l.scan_tokens();
-        l
+        l.scan_tokens()?;
+        Ok(l)
     }
 
     pub fn emit(&self) {
@@ -31,40 +120,54 @@ impl Lexer {
         }
     }
 
-    fn error(&self, message: String, line: u32, col: u32) {
-        panic!("{} at {}:{}", message, line, col);
+    /// Fails with an error spanning the current lexeme.
+    fn error(&self, kind: LexErrorKind) -> Result<(), LexError> {
+        self.error_from(kind, self.start_idx, self.start_loc)
+    }
+
+    /// Fails with an error spanning from inside the current lexeme, such as a
+    /// bad escape, to the current position.
+    fn error_from(&self, kind: LexErrorKind, start: usize, start_loc: Loc) -> Result<(), LexError> {
+        Err(LexError {
+            kind,
+            span: Span::new(start, self.curr_idx, start_loc, self.curr_loc),
+        })
     }
 
     fn is_at_end(&self) -> bool {
-        self.curr_idx >= self.source.len() as u32
+        self.curr_idx >= self.source.len()
+    }
+
+    /// The source from the current position on.
+    fn rest(&self) -> &str {
+        &self.source[self.curr_idx..]
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.curr_idx as usize).unwrap()
-        }
+        self.rest().chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.curr_idx + 1 >= self.source.len() as u32 {
-            '\0'
-        } else {
-            self.source
-                .chars()
-                .nth((self.curr_idx + 1) as usize)
-                .unwrap()
-        }
+        self.rest().chars().nth(1).unwrap_or('\0')
+    }
+
+    /// Where the token being scanned lies in the source.
+    fn span(&self) -> Span {
+        Span::new(self.start_idx, self.curr_idx, self.start_loc, self.curr_loc)
+    }
+
+    /// The source text of the token being scanned.
+    fn lexeme(&self) -> String {
+        self.source[self.start_idx..self.curr_idx].to_string()
     }
 
     fn increment_position(&mut self) {
-        if self.peek() == '\n' {
-            self.curr_idx += 1;
+        let c = self.peek();
+        self.curr_idx += c.len_utf8();
+        if c == '\n' {
             self.curr_loc.line += 1;
             self.curr_loc.col = 1;
         } else {
-            self.curr_idx += 1;
             self.curr_loc.col += 1;
         }
     }
@@ -76,9 +179,7 @@ impl Lexer {
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.peek() != expected {
+        if self.is_at_end() || self.peek() != expected {
             false
         } else {
             self.increment_position();
@@ -86,106 +187,122 @@ impl Lexer {
         }
     }
 
-    fn scan_tokens(&mut self) {
+    fn scan_tokens(&mut self) -> Result<(), LexError> {
         while !self.is_at_end() {
             self.start_idx = self.curr_idx;
             self.start_loc = self.curr_loc;
-            self.scan_token();
+            self.scan_longest_token()?;
         }
         self.start_idx = self.curr_idx;
         self.start_loc = self.curr_loc;
         // self.tokens
-        // .push(Token::new(self.start_loc, self.start_loc, TokenValue::EOF));
+        // .push(Token::new(self.span(), TokenValue::EOF));
+        Ok(())
     }
 
     fn is_digit(c: char) -> bool {
-        match c {
-            '0'..='9' => true,
-            _ => false,
-        }
+        c.is_ascii_digit()
     }
+    /// Identifiers follow Unicode's XID rules, so `θ` and `résultat` are names,
+    /// with `_` also allowed first.
     fn is_alpha(c: char) -> bool {
-        match c {
-            'A'..='Z' | 'a'..='z' | '_' => true,
-            _ => false,
-        }
+        c == '_' || c.is_xid_start()
     }
     fn is_alphanumeric(c: char) -> bool {
-        Self::is_digit(c) || Self::is_alpha(c)
+        c.is_xid_continue()
+    }
+
+    /// Whether `c` belongs to the name or number before it. Glyphs such as `·`
+    /// and `π` are letters to Unicode, but end the word when read as glyphs.
+    fn continues_word(&self, c: char) -> bool {
+        Self::is_alphanumeric(c) && !(self.config.math_glyphs && math_glyph(c).is_some())
+    }
+
+    /// The longest declared operator symbol starting at the current position.
+    fn match_operator(&self) -> Option<String> {
+        let rest = self.rest();
+        self.config
+            .operators
+            .iter()
+            .filter(|symbol| !symbol.is_empty() && rest.starts_with(symbol.as_str()))
+            .max_by_key(|symbol| symbol.len())
+            .cloned()
+    }
+
+    /// Scans the next token, preferring a declared operator symbol unless the
+    /// built-in token at the same position is longer.
+    fn scan_longest_token(&mut self) -> Result<(), LexError> {
+        let Some(symbol) = self.match_operator() else {
+            return self.scan_token();
+        };
+        let token_count = self.tokens.len();
+        let builtin = self.scan_token();
+        let builtin_len = self.curr_idx - self.start_idx;
+        let builtin_added = self.tokens.len() > token_count;
+        if builtin.is_ok() && builtin_added && builtin_len > symbol.len() {
+            return Ok(());
+        }
+
+        self.tokens.truncate(token_count);
+        self.curr_idx = self.start_idx;
+        self.curr_loc = self.start_loc;
+        for _ in symbol.chars() {
+            self.increment_position();
+        }
+        self.add_token(Token::new(self.span(), TokenValue::Operator(symbol)));
+        Ok(())
     }
 
-    fn scan_token(&mut self) {
+    fn scan_token(&mut self) -> Result<(), LexError> {
         let c: char = self.next();
         match c {
-            '(' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::LeftParen,
-            )),
-            ')' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::RightParen,
-            )),
-            '{' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::LeftBrace,
-            )),
-            '}' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::RightBrace,
-            )),
-            ',' => self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Comma)),
-            '.' => self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Dot)),
-            ';' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::Semicolon,
-            )),
+            '(' => self.add_token(Token::new(self.span(), TokenValue::LeftParen)),
+            ')' => self.add_token(Token::new(self.span(), TokenValue::RightParen)),
+            '{' => self.add_token(Token::new(self.span(), TokenValue::LeftBrace)),
+            '}' => self.add_token(Token::new(self.span(), TokenValue::RightBrace)),
+            ',' => self.add_token(Token::new(self.span(), TokenValue::Comma)),
+            '[' => self.add_token(Token::new(self.span(), TokenValue::LeftBracket)),
+            ']' => self.add_token(Token::new(self.span(), TokenValue::RightBracket)),
+            '.' => self.add_token(Token::new(self.span(), TokenValue::Dot)),
+            ';' => self.add_token(Token::new(self.span(), TokenValue::Semicolon)),
+            '?' => self.add_token(Token::new(self.span(), TokenValue::Question)),
             ':' => {
                 if self.match_char(':') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::ColonColon,
-                    ));
+                    self.add_token(Token::new(self.span(), TokenValue::ColonColon));
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Colon));
+                    self.add_token(Token::new(self.span(), TokenValue::Colon));
                 }
             }
             '+' => {
-                if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::PlusEqual,
-                    ))
+                if self.match_char('+') {
+                    self.add_token(Token::new(self.span(), TokenValue::PlusPlus))
+                } else if self.match_char('=') {
+                    self.add_token(Token::new(self.span(), TokenValue::PlusEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Plus))
+                    self.add_token(Token::new(self.span(), TokenValue::Plus))
                 }
             }
             '-' => {
-                if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::MinusEqual,
-                    ))
+                if self.match_char('-') {
+                    self.add_token(Token::new(self.span(), TokenValue::MinusMinus))
+                } else if self.match_char('=') {
+                    self.add_token(Token::new(self.span(), TokenValue::MinusEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Minus))
+                    self.add_token(Token::new(self.span(), TokenValue::Minus))
                 }
             }
             '*' => {
-                if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::StarEqual,
-                    ))
+                if self.match_char('*') {
+                    let value = if self.match_char('=') {
+                        TokenValue::StarStarEqual
+                    } else {
+                        TokenValue::StarStar
+                    };
+                    self.add_token(Token::new(self.span(), value))
+                } else if self.match_char('=') {
+                    self.add_token(Token::new(self.span(), TokenValue::StarEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Star))
+                    self.add_token(Token::new(self.span(), TokenValue::Star))
                 }
             }
             '/' => {
@@ -194,176 +311,104 @@ impl Lexer {
                         self.increment_position();
                     }
                 } else if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::SlashEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::SlashEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Slash))
+                    self.add_token(Token::new(self.span(), TokenValue::Slash))
                 }
             }
             '%' => {
                 if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::ModEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::ModEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Mod))
+                    self.add_token(Token::new(self.span(), TokenValue::Mod))
                 }
             }
             '=' => {
                 if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::EqualEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::EqualEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Equal))
+                    self.add_token(Token::new(self.span(), TokenValue::Equal))
                 }
             }
+            '\'' => self.add_token(Token::new(self.span(), TokenValue::Quote)),
             '!' => {
                 if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::NotEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::NotEqual))
                 } else {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::LogNot,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::LogNot))
                 }
             }
             '<' => {
                 if self.match_char('<') {
                     if self.match_char('=') {
-                        self.add_token(Token::new(
-                            self.start_loc,
-                            self.curr_loc,
-                            TokenValue::LeftShiftEqual,
-                        ))
+                        self.add_token(Token::new(self.span(), TokenValue::LeftShiftEqual))
                     } else {
-                        self.add_token(Token::new(
-                            self.start_loc,
-                            self.curr_loc,
-                            TokenValue::LeftShift,
-                        ))
+                        self.add_token(Token::new(self.span(), TokenValue::LeftShift))
                     }
                 } else if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::LessEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::LessEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::Less))
+                    self.add_token(Token::new(self.span(), TokenValue::Less))
                 }
             }
             '>' => {
                 if self.match_char('>') {
                     if self.match_char('=') {
-                        self.add_token(Token::new(
-                            self.start_loc,
-                            self.curr_loc,
-                            TokenValue::RightShiftEqual,
-                        ))
+                        self.add_token(Token::new(self.span(), TokenValue::RightShiftEqual))
                     } else {
-                        self.add_token(Token::new(
-                            self.start_loc,
-                            self.curr_loc,
-                            TokenValue::RightShift,
-                        ))
+                        self.add_token(Token::new(self.span(), TokenValue::RightShift))
                     }
                 } else if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::GreaterEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::GreaterEqual))
                 } else {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::Greater,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::Greater))
                 }
             }
             '&' => {
                 if self.match_char('&') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::LogAnd,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::LogAnd))
                 } else if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::BitAndEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::BitAndEqual))
                 } else {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::BitAnd,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::BitAnd))
                 }
             }
             '|' => {
                 if self.match_char('|') {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::LogOr))
+                    self.add_token(Token::new(self.span(), TokenValue::LogOr))
                 } else if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::BitOrEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::BitOrEqual))
                 } else {
-                    self.add_token(Token::new(self.start_loc, self.curr_loc, TokenValue::BitOr))
+                    self.add_token(Token::new(self.span(), TokenValue::BitOr))
                 }
             }
-            '~' => self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::BitNot,
-            )),
+            '~' => self.add_token(Token::new(self.span(), TokenValue::BitNot)),
             '^' => {
                 if self.match_char('=') {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::BitXorEqual,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::BitXorEqual))
                 } else {
-                    self.add_token(Token::new(
-                        self.start_loc,
-                        self.curr_loc,
-                        TokenValue::BitXor,
-                    ))
+                    self.add_token(Token::new(self.span(), TokenValue::BitXor))
                 }
             }
-            '"' => self.scan_string(),
+            '"' => return self.scan_string(),
             ' ' | '\r' | '\t' | '\n' => (),
-            '0'..='9' => self.scan_number(),
-            'A'..='Z' | 'a'..='z' | '_' => self.scan_identifier(),
-
-            _ => self.error(
-                "Unexpected character".to_string(),
-                self.start_loc.line,
-                self.start_loc.col,
-            ),
+            '0'..='9' => return self.scan_number(),
+            // Before identifiers, as `π` is also a letter
+            c if self.config.math_glyphs && math_glyph(c).is_some() => {
+                let value = math_glyph(c).unwrap();
+                self.add_token(Token::new(self.span(), value))
+            }
+            c if Self::is_alpha(c) => self.scan_identifier(),
+
+            _ => return self.error(LexErrorKind::UnexpectedCharacter(c)),
         }
+        Ok(())
     }
 
-    fn scan_string(&mut self) {
+    fn scan_string(&mut self) -> Result<(), LexError> {
         let mut str = String::new();
         while self.peek() != '"' && !self.is_at_end() {
+            let (escape_idx, escape_loc) = (self.curr_idx, self.curr_loc);
             let c = self.next();
             if c == '\\' {
                 if self.match_char('0') {
@@ -378,12 +423,11 @@ impl Lexer {
                     str.push('\\');
                 } else if self.match_char('"') {
                     str.push('"');
+                } else if self.is_at_end() {
+                    return self.error(LexErrorKind::UnterminatedString);
                 } else {
-                    self.error(
-                        "Invalid escape sequence".to_string(),
-                        self.curr_loc.line,
-                        self.curr_loc.col,
-                    );
+                    let c = self.next();
+                    return self.error_from(LexErrorKind::InvalidEscape(c), escape_idx, escape_loc);
                 }
             } else {
                 str.push(c);
@@ -391,78 +435,123 @@ impl Lexer {
         }
 
         if self.is_at_end() {
-            self.error(
-                "Unterminated string".to_string(),
-                self.curr_loc.line,
-                self.curr_loc.col,
-            );
+            return self.error(LexErrorKind::UnterminatedString);
         }
 
         self.next();
-        self.add_token(Token::new(
-            self.start_loc,
-            self.curr_loc,
-            TokenValue::String(str),
-        ));
+        self.add_token(Token::new(self.span(), TokenValue::String(str)));
+        Ok(())
     }
 
-    fn scan_number(&mut self) {
-        while Self::is_digit(self.peek()) {
+    /// Scans a number whose first digit has been consumed: `0x`, `0o` and `0b`
+    /// integers, and decimal integers or floats with optional fraction and
+    /// exponent. `_` may separate digits anywhere after the first.
+    fn scan_number(&mut self) -> Result<(), LexError> {
+        let first = self.lexeme();
+        let radix = match (first.as_str(), self.peek()) {
+            ("0", 'x' | 'X') => 16,
+            ("0", 'o' | 'O') => 8,
+            ("0", 'b' | 'B') => 2,
+            _ => 10,
+        };
+        if radix != 10 {
             self.increment_position();
+            let digits = self.scan_digits(radix)?;
+            // A letter or out-of-range digit right after the prefix, as in `0o8`,
+            // is an invalid digit rather than a missing one
+            self.check_number_end(radix)?;
+            if digits.is_empty() {
+                return self.error(LexErrorKind::MissingDigits(self.lexeme()));
+            }
+            self.add_token(Token::new(self.span(), Self::integer(&digits, radix)));
+            return Ok(());
+        }
+
+        let mut literal = first + &self.scan_digits(10)?;
+        let mut float = false;
+        if self.peek() == '.' && Self::is_digit(self.peek_next()) {
+            literal.push(self.next());
+            literal += &self.scan_digits(10)?;
+            float = true;
+        }
+        if matches!(self.peek(), 'e' | 'E') {
+            literal.push(self.next());
+            if matches!(self.peek(), '+' | '-') {
+                literal.push(self.next());
+            }
+            let exponent = self.scan_digits(10)?;
+            if exponent.is_empty() {
+                return self.error(LexErrorKind::MissingExponent(self.lexeme()));
+            }
+            literal += &exponent;
+            float = true;
         }
-        if !(self.peek() == '.' && Self::is_digit(self.peek_next())) {
-            self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::Integer(
-                    self.source
-                        .get((self.start_idx as usize)..(self.curr_idx as usize))
-                        .unwrap()
-                        .parse::<i64>()
-                        .unwrap(),
-                ),
-            ));
+        self.check_number_end(10)?;
+
+        let value = if float {
+            TokenValue::Float(literal.parse::<f64>().unwrap())
         } else {
-            self.increment_position();
-            while self.peek().is_ascii_digit() {
-                self.increment_position();
+            Self::integer(&literal, 10)
+        };
+        self.add_token(Token::new(self.span(), value));
+        Ok(())
+    }
+
+    /// The value of a run of digits, kept exactly when it does not fit in `i64`.
+    fn integer(digits: &str, radix: u32) -> TokenValue {
+        match i64::from_str_radix(digits, radix) {
+            Ok(value) => TokenValue::Integer(value),
+            Err(_) => {
+                TokenValue::BigInteger(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap())
+            }
+        }
+    }
+
+    /// Consumes digits of `radix` and `_` separators, returning the digits alone.
+    fn scan_digits(&mut self, radix: u32) -> Result<String, LexError> {
+        let mut digits = String::new();
+        let mut separated = false;
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            let c = self.next();
+            if c == '_' && separated {
+                self.error(LexErrorKind::MisplacedSeparator(self.lexeme()))?;
             }
-            self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::Float(
-                    self.source
-                        .get((self.start_idx as usize)..(self.curr_idx as usize))
-                        .unwrap()
-                        .parse::<f64>()
-                        .unwrap(),
-                ),
-            ));
+            separated = c == '_';
+            if !separated {
+                digits.push(c);
+            }
+        }
+        if separated {
+            self.error(LexErrorKind::MisplacedSeparator(self.lexeme()))?;
+        }
+        Ok(digits)
+    }
+
+    /// Rejects a letter or digit run into the end of a number: an invalid digit
+    /// after a radix prefix, as in `0b102`, or a suffix on a decimal, as in `2x`.
+    fn check_number_end(&mut self, radix: u32) -> Result<(), LexError> {
+        if self.continues_word(self.peek()) {
+            let (digit_idx, digit_loc) = (self.curr_idx, self.curr_loc);
+            let digit = self.next();
+            let kind = match radix {
+                10 => LexErrorKind::InvalidSuffix(digit),
+                _ => LexErrorKind::InvalidDigit { digit, radix },
+            };
+            return self.error_from(kind, digit_idx, digit_loc);
         }
+        Ok(())
     }
 
     fn scan_identifier(&mut self) {
-        while Self::is_alphanumeric(self.peek()) {
+        while self.continues_word(self.peek()) {
             self.increment_position();
         }
 
-        let word = self
-            .source
-            .get((self.start_idx as usize)..(self.curr_idx as usize))
-            .unwrap()
-            .to_string();
+        let word = self.lexeme();
         if let Some(token_value) = KEYWORD_TABLE.get(&word) {
-            self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                token_value.clone(),
-            ));
+            self.add_token(Token::new(self.span(), token_value.clone()));
         } else {
-            self.add_token(Token::new(
-                self.start_loc,
-                self.curr_loc,
-                TokenValue::Identifier(word),
-            ));
+            self.add_token(Token::new(self.span(), TokenValue::Identifier(word)));
         }
     }
 
@@ -470,3 +559,88 @@ impl Lexer {
         self.tokens.push(token);
     }
 }
+
+/// The token a mathematical glyph stands for: `×`, `⋅`, `·`, `÷`, `−`, `≤`, `≥`,
+/// `≠`, `¬`, `∧`, `∨` and `√` as operators, and `π` as `consts::PI`.
+pub fn math_glyph(c: char) -> Option<TokenValue> {
+    let value = match c {
+        '×' | '⋅' | '·' => TokenValue::Star,
+        '÷' => TokenValue::Slash,
+        '−' => TokenValue::Minus,
+        '≤' => TokenValue::LessEqual,
+        '≥' => TokenValue::GreaterEqual,
+        '≠' => TokenValue::NotEqual,
+        '¬' => TokenValue::LogNot,
+        '∧' => TokenValue::LogAnd,
+        '∨' => TokenValue::LogOr,
+        'π' => TokenValue::Identifier("consts::PI".to_string()),
+        '√' => TokenValue::Root,
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> LexErrorKind {
+        Lexer::new(source.to_string()).unwrap_err().kind
+    }
+
+    #[test]
+    fn malformed_numbers_are_rejected_precisely() {
+        assert_eq!(
+            error("1__0"),
+            LexErrorKind::MisplacedSeparator("1__".to_string())
+        );
+        assert_eq!(
+            error("0o8"),
+            LexErrorKind::InvalidDigit {
+                digit: '8',
+                radix: 8
+            }
+        );
+        assert_eq!(
+            error("0b2"),
+            LexErrorKind::InvalidDigit {
+                digit: '2',
+                radix: 2
+            }
+        );
+        assert_eq!(error("0x"), LexErrorKind::MissingDigits("0x".to_string()));
+        assert_eq!(
+            error("0xg"),
+            LexErrorKind::InvalidDigit {
+                digit: 'g',
+                radix: 16
+            }
+        );
+    }
+
+    #[test]
+    fn letter_after_decimal_is_a_suffix() {
+        assert_eq!(error("2x"), LexErrorKind::InvalidSuffix('x'));
+        assert_eq!(error("1.5e3π"), LexErrorKind::InvalidSuffix('π'));
+    }
+
+    #[test]
+    fn glyphs_end_names_and_numbers() {
+        let config = LexerConfig {
+            math_glyphs: true,
+            ..LexerConfig::default()
+        };
+        let lexer = Lexer::with_config("√a·2·b".to_string(), config).unwrap();
+        let tokens: Vec<String> = lexer.tokens.iter().map(|t| t.value.to_string()).collect();
+        assert_eq!(tokens, ["√", "a", "*", "2", "*", "b"]);
+    }
+
+    #[test]
+    fn errors_span_the_offending_text() {
+        let source = "x = \"a\\qb\"";
+        let e = Lexer::new(source.to_string()).unwrap_err();
+        assert_eq!(&source[e.span().range()], "\\q");
+        let e = Lexer::new("0b12".to_string()).unwrap_err();
+        assert_eq!(e.span().range(), 3..4);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod ast;
+pub mod eval;
+pub mod lexer;
+pub mod operators;
+pub mod parser;
+pub mod registry;
+pub mod source;
+pub mod token;
+pub mod trace;
+pub mod tui;
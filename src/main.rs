@@ -1,29 +1,331 @@
-pub mod lexer;
-pub mod parser;
-pub mod token;
-
-use lexer::Lexer;
-use parser::ShuntingYardParser as SYParser;
+use shunting_yard_visualizer::eval::{Arithmetic, Environment, Evaluator, Value};
+use shunting_yard_visualizer::lexer::{Lexer, LexerConfig};
+use shunting_yard_visualizer::operators::{Assoc, Fixity, OperatorTable};
+use shunting_yard_visualizer::parser::{Program, ShuntingYardParser as SYParser};
+use shunting_yard_visualizer::registry::Registry;
+use shunting_yard_visualizer::source::SourceFile;
+use shunting_yard_visualizer::token::{Span, TokenValue};
+use shunting_yard_visualizer::tui;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
 // use std::fs::read;
-use token::{Loc, Token, TokenValue};
-
-fn main() {
-    let lexer: Lexer;
-
-    // lexer = Lexer::new(String::from_utf8(read("example_program.fp").unwrap()).unwrap());
-    // lexer.emit();
-    // println!();
-    // lexer = Lexer::new("123. 123.456 2193\n,391048".to_string());
-    // lexer.emit();
-    // println!();
-    // lexer = Lexer::new("if (x > 3) { print(\"greater\"); } else { print(\"less\") }".to_string());
-    // lexer.emit();
-    lexer = Lexer::new("x = max(10, 1309, x * 2 + y)".to_string());
+
+const USAGE: &str =
+    "usage: shunting-yard-visualizer [-i | --interactive | -r | --repl] [--exact] [--math-glyphs] [--dialect c|math] [--operator FIXITY:PRECEDENCE:ASSOC:SYMBOL]... [--set NAME=VALUE]... [EXPRESSION]";
+
+fn main() -> ExitCode {
+    let mut interactive = false;
+    let mut repl = false;
+    let mut arithmetic = Arithmetic::Native;
+    let mut math_glyphs = false;
+    let mut source = None;
+    let mut environment = Environment::new();
+    let mut operators = OperatorTable::default();
+    let mut declarations = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" | "--interactive" => interactive = true,
+            "-r" | "--repl" => repl = true,
+            "--exact" => arithmetic = Arithmetic::Exact,
+            "--math-glyphs" => math_glyphs = true,
+            "--dialect" => {
+                let Some(table) = args.next().as_deref().and_then(OperatorTable::preset) else {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                };
+                operators = table;
+            }
+            "--operator" => {
+                let Some(declaration) = args.next().as_deref().and_then(parse_operator) else {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                };
+                declarations.push(declaration);
+            }
+            "--set" => {
+                let Some((name, value)) = args.next().as_deref().and_then(parse_binding) else {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                };
+                environment.set(&name, value);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if source.is_none() => source = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    // Declared after the dialect is chosen, whatever the order on the command line
+    for (symbol, fixity, precedence, assoc) in declarations {
+        operators.declare(&symbol, fixity, precedence, assoc);
+    }
+    let config = LexerConfig {
+        operators: operators.symbols(),
+        math_glyphs,
+    };
+
+    if repl {
+        return run_repl(&mut environment, &operators, &config, arithmetic);
+    }
+    let source = source.unwrap_or_else(|| {
+        // The demo reads `x` and `y`, so give them values unless set already
+        for (name, value) in [("x", 3), ("y", 4)] {
+            if environment.get(name).is_none() {
+                environment.set(name, Value::Integer(value));
+            }
+        }
+        "x = max(10, 1309, x * 2 + y)".to_string()
+    });
+
+    // let lexer = Lexer::new(String::from_utf8(read("example_program.fp").unwrap()).unwrap());
+    // let lexer = Lexer::new("123. 123.456 2193\n,391048".to_string());
+    // let lexer = Lexer::new("if (x > 3) { print(\"greater\"); } else { print(\"less\") }".to_string());
+    let file = SourceFile::new("<expression>", source.clone());
+    let lexer = match Lexer::with_config(source.clone(), config) {
+        Ok(lexer) => lexer,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            excerpt(&file, e.span());
+            return ExitCode::FAILURE;
+        }
+    };
+    let program = Program::new(lexer.tokens.clone(), &Registry::default(), &operators);
+
+    // Statements run in order against one environment, so later statements see
+    // the assignments of earlier ones. Evaluation stops at the first failure.
+    // Statements using declared operators are only parsed.
+    let mut evaluators = Vec::new();
+    if program.error().is_none() {
+        for statement in program.statements() {
+            if uses_declared_operator(statement) {
+                evaluators.push(None);
+                continue;
+            }
+            let evaluator = Evaluator::new(&statement.output_queue, &mut environment, arithmetic);
+            let failed = evaluator.error().is_some();
+            evaluators.push(Some(evaluator));
+            if failed {
+                break;
+            }
+        }
+    }
+
+    if interactive {
+        let statements = program.statements();
+        let mut stepper = tui::Stepper::new(&source, statements[0].trace());
+        for (i, statement) in statements.iter().enumerate() {
+            if i > 0 {
+                stepper = stepper.with_statement(statement.trace());
+            }
+            if let Some(Some(evaluator)) = evaluators.get(i) {
+                stepper = stepper.with_evaluation(evaluator.trace());
+            }
+        }
+        if let Err(e) = stepper.run() {
+            eprintln!("terminal error: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
     lexer.emit();
+    for (i, parser) in program.statements().iter().enumerate() {
+        println!();
+        if program.statements().len() > 1 {
+            println!("-- statement {} --", i + 1);
+            println!();
+        }
+        if let Err(code) = report(&file, parser, evaluators.get(i).and_then(Option::as_ref)) {
+            return code;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the RPN, trace, tree and evaluation of one statement.
+fn report(
+    file: &SourceFile,
+    parser: &SYParser,
+    evaluator: Option<&Evaluator>,
+) -> Result<(), ExitCode> {
+    parser.emit();
     println!();
 
-    let parser: SYParser;
+    for step in parser.trace().steps() {
+        println!(
+            "{:?} {:?} -> {:?}: {:?}",
+            step.token.value,
+            step.from,
+            step.to,
+            step.rule.describe()
+        );
+    }
+    if let Some(ast) = parser.ast() {
+        println!();
+        println!("{}", ast);
+        print!("{}", ast.tree());
+    }
+    if let Some(e) = parser.error() {
+        eprintln!("error: {}", e);
+        excerpt(file, e.span());
+        return Err(ExitCode::FAILURE);
+    }
+    if uses_declared_operator(parser) {
+        println!();
+        println!("not evaluated: declared operators have a precedence but no meaning");
+    }
+    if let Some(evaluator) = evaluator {
+        println!();
+        for step in evaluator.trace().steps() {
+            let stack: Vec<String> = step
+                .state
+                .value_stack
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            println!("{:<8} [{}]", step.token.to_string(), stack.join(", "));
+        }
+        match (evaluator.result(), evaluator.error()) {
+            (_, Some(e)) => {
+                eprintln!("error: {}", e);
+                excerpt(file, e.span());
+                return Err(ExitCode::FAILURE);
+            }
+            (Some(value), None) => println!("= {}", value),
+            (None, None) => (),
+        }
+    }
+    Ok(())
+}
 
-    parser = SYParser::new(lexer.tokens);
-    parser.emit();
+/// Whether a statement applies an operator declared with `--operator`, which
+/// the evaluator cannot give a meaning to.
+fn uses_declared_operator(statement: &SYParser) -> bool {
+    statement
+        .output_queue
+        .iter()
+        .any(|t| matches!(t.value, TokenValue::CustomOp(..)))
+}
+
+/// Prints the source line `span` starts on, with the span underlined.
+fn excerpt(file: &SourceFile, span: Span) {
+    let Some(line) = file.line(span.start_loc.line) else {
+        return;
+    };
+    let indent = span.start_loc.col as usize - 1;
+    let width = file
+        .slice(span)
+        .map_or(0, |text| text.chars().count())
+        .min(line.chars().count().saturating_sub(indent))
+        .max(1);
+    eprintln!("  | {}", line);
+    eprintln!("  | {}{}", " ".repeat(indent), "^".repeat(width));
+}
+
+/// Reads one expression per line from stdin and evaluates each against the same
+/// environment, so assignments carry over to later lines.
+fn run_repl(
+    environment: &mut Environment,
+    operators: &OperatorTable,
+    config: &LexerConfig,
+    arithmetic: Arithmetic,
+) -> ExitCode {
+    let registry = Registry::default();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+            None => return ExitCode::SUCCESS,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let lexer = match Lexer::with_config(line, config.clone()) {
+            Ok(lexer) => lexer,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                continue;
+            }
+        };
+        let program = Program::new(lexer.tokens, &registry, operators);
+        if let Some(e) = program.error() {
+            eprintln!("error: {}", e);
+            continue;
+        }
+        for statement in program.statements() {
+            if uses_declared_operator(statement) {
+                println!("not evaluated: declared operators have a precedence but no meaning");
+                continue;
+            }
+            let evaluator = Evaluator::new(&statement.output_queue, environment, arithmetic);
+            match (evaluator.result(), evaluator.error()) {
+                (_, Some(e)) => {
+                    eprintln!("error: {}", e);
+                    break;
+                }
+                (Some(value), None) => println!("{}", value),
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+/// Parses an `--operator` argument such as `infix:9:left:<=>`. The symbol comes
+/// last so it may itself contain `:`.
+fn parse_operator(arg: &str) -> Option<(String, Fixity, u8, Assoc)> {
+    let mut parts = arg.splitn(4, ':');
+    let fixity = match parts.next()? {
+        "prefix" => Fixity::Prefix,
+        "infix" => Fixity::Infix,
+        "postfix" => Fixity::Postfix,
+        _ => return None,
+    };
+    let precedence = parts.next()?.parse().ok()?;
+    let assoc = match parts.next()? {
+        "left" => Assoc::Left,
+        "right" => Assoc::Right,
+        _ => return None,
+    };
+    let symbol = parts.next()?;
+    // Letters, digits and whitespace would swallow parts of identifiers
+    if symbol.is_empty()
+        || symbol
+            .chars()
+            .any(|c| c.is_alphanumeric() || c == '_' || c.is_whitespace())
+    {
+        return None;
+    }
+    Some((symbol.to_string(), fixity, precedence, assoc))
+}
+
+/// Parses a `--set` argument such as `x=3`, `y=2.5`, `flag=true` or `s="text"`.
+fn parse_binding(arg: &str) -> Option<(String, Value)> {
+    let (name, value) = arg.split_once('=')?;
+    let value = match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" => Value::Null,
+        _ if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') => {
+            Value::String(value[1..value.len() - 1].to_string())
+        }
+        _ => match value.parse::<i64>() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Float(value.parse::<f64>().ok()?),
+        },
+    };
+    Some((name.to_string(), value))
 }
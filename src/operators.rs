@@ -0,0 +1,234 @@
+use crate::token::TokenValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    Prefix,
+    Infix,
+    Postfix,
+}
+
+/// How one token behaves when it is used as an operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operator {
+    /// The token as produced by the lexer.
+    pub token: TokenValue,
+    /// The operation the token stands for. The parser replaces the token with it
+    /// once classified, e.g. `-` becomes `u-` before an operand and `^` becomes
+    /// `**` in math notation, so evaluation does not depend on the dialect.
+    pub value: TokenValue,
+    pub fixity: Fixity,
+    pub precedence: u8,
+    pub assoc: Assoc,
+}
+
+/// The operators the parser accepts, with their precedence and associativity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperatorTable {
+    operators: Vec<Operator>,
+}
+
+impl OperatorTable {
+    /// An empty table, in which every operator token is unexpected.
+    pub fn new() -> Self {
+        Self {
+            operators: Vec::new(),
+        }
+    }
+
+    /// C precedence rules, plus `**` for right-associative exponentiation,
+    /// postfix `!` for factorial and `'` for prime, and `a.b` and `a[i]`.
+    pub fn c_like() -> Self {
+        use Assoc::{Left, Right};
+        use TokenValue as T;
+
+        let mut table = Self::new();
+        for (token, precedence, assoc) in [
+            (T::Equal, 1, Right),
+            (T::PlusEqual, 1, Right),
+            (T::MinusEqual, 1, Right),
+            (T::StarEqual, 1, Right),
+            (T::SlashEqual, 1, Right),
+            (T::ModEqual, 1, Right),
+            (T::StarStarEqual, 1, Right),
+            (T::BitAndEqual, 1, Right),
+            (T::BitOrEqual, 1, Right),
+            (T::BitXorEqual, 1, Right),
+            (T::LeftShiftEqual, 1, Right),
+            (T::RightShiftEqual, 1, Right),
+            // Looser than everything but assignment, so `a || b ? c : d` tests
+            // `a || b`. Right-associative so `a ? b : c ? d : e` nests to the right.
+            (T::Question, 2, Right),
+            (T::LogOr, 3, Left),
+            (T::LogAnd, 4, Left),
+            (T::BitOr, 5, Left),
+            (T::BitXor, 6, Left),
+            (T::BitAnd, 7, Left),
+            (T::EqualEqual, 8, Left),
+            (T::NotEqual, 8, Left),
+            (T::Less, 9, Left),
+            (T::LessEqual, 9, Left),
+            (T::Greater, 9, Left),
+            (T::GreaterEqual, 9, Left),
+            (T::LeftShift, 10, Left),
+            (T::RightShift, 10, Left),
+            (T::Plus, 11, Left),
+            (T::Minus, 11, Left),
+            (T::Star, 12, Left),
+            (T::Slash, 12, Left),
+            (T::Mod, 12, Left),
+            // Above the prefix operators, so `-2 ** 2` is `-(2 ** 2)`
+            (T::StarStar, 14, Right),
+            // Member access binds tightest, so `-a.b ** 2` is `-((a.b) ** 2)`
+            (T::Dot, 16, Left),
+        ] {
+            let value = match token {
+                T::Question => T::Ternary,
+                _ => token.clone(),
+            };
+            table.define(token, value, Fixity::Infix, precedence, assoc);
+        }
+        table
+            .define(T::Plus, T::UnaryPlus, Fixity::Prefix, 13, Right)
+            .define(T::Minus, T::UnaryMinus, Fixity::Prefix, 13, Right)
+            .define(T::LogNot, T::LogNot, Fixity::Prefix, 13, Right)
+            .define(T::BitNot, T::BitNot, Fixity::Prefix, 13, Right)
+            .define(T::Root, T::Root, Fixity::Prefix, 13, Right)
+            .define(T::PlusPlus, T::PostIncrement, Fixity::Postfix, 15, Left)
+            .define(T::MinusMinus, T::PostDecrement, Fixity::Postfix, 15, Left)
+            .define(T::LogNot, T::Factorial, Fixity::Postfix, 15, Left)
+            .define(T::Quote, T::Prime, Fixity::Postfix, 15, Left)
+            // Resolved to `Index(n)` once the `]` is found
+            .define(T::LeftBracket, T::Index(0), Fixity::Postfix, 16, Left);
+        table
+    }
+
+    /// Arithmetic as written on paper: `^` is right-associative power, `!` is
+    /// factorial and there is no assignment, logic or bit manipulation.
+    pub fn math() -> Self {
+        use Assoc::{Left, Right};
+        use TokenValue as T;
+
+        let mut table = Self::new();
+        table
+            .define(T::EqualEqual, T::EqualEqual, Fixity::Infix, 8, Left)
+            .define(T::NotEqual, T::NotEqual, Fixity::Infix, 8, Left)
+            .define(T::Less, T::Less, Fixity::Infix, 9, Left)
+            .define(T::LessEqual, T::LessEqual, Fixity::Infix, 9, Left)
+            .define(T::Greater, T::Greater, Fixity::Infix, 9, Left)
+            .define(T::GreaterEqual, T::GreaterEqual, Fixity::Infix, 9, Left)
+            .define(T::Plus, T::Plus, Fixity::Infix, 11, Left)
+            .define(T::Minus, T::Minus, Fixity::Infix, 11, Left)
+            .define(T::Star, T::Star, Fixity::Infix, 12, Left)
+            .define(T::Slash, T::Slash, Fixity::Infix, 12, Left)
+            .define(T::Plus, T::UnaryPlus, Fixity::Prefix, 13, Right)
+            .define(T::Minus, T::UnaryMinus, Fixity::Prefix, 13, Right)
+            .define(T::Root, T::Root, Fixity::Prefix, 13, Right)
+            .define(T::BitXor, T::StarStar, Fixity::Infix, 14, Right)
+            .define(T::LogNot, T::Factorial, Fixity::Postfix, 15, Left)
+            .define(T::Quote, T::Prime, Fixity::Postfix, 15, Left);
+        table
+    }
+
+    /// Looks up a preset by name, as given on the command line.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "c" => Some(Self::c_like()),
+            "math" => Some(Self::math()),
+            _ => None,
+        }
+    }
+
+    /// Makes `token` an operator standing for `value`, replacing whatever the
+    /// token meant before with the same fixity.
+    pub fn define(
+        &mut self,
+        token: TokenValue,
+        value: TokenValue,
+        fixity: Fixity,
+        precedence: u8,
+        assoc: Assoc,
+    ) -> &mut Self {
+        self.remove(&token, fixity);
+        self.operators.push(Operator {
+            token,
+            value,
+            fixity,
+            precedence,
+            assoc,
+        });
+        self
+    }
+
+    /// Declares `symbol` as a new operator. The lexer has to be told about the
+    /// symbol too, see [`Self::symbols`].
+    pub fn declare(
+        &mut self,
+        symbol: &str,
+        fixity: Fixity,
+        precedence: u8,
+        assoc: Assoc,
+    ) -> &mut Self {
+        self.define(
+            TokenValue::Operator(symbol.to_string()),
+            TokenValue::CustomOp(symbol.to_string(), fixity),
+            fixity,
+            precedence,
+            assoc,
+        )
+    }
+
+    /// The symbols of all declared operators, for the lexer to recognize.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols = Vec::new();
+        for op in &self.operators {
+            if let TokenValue::Operator(symbol) = &op.token {
+                if !symbols.contains(symbol) {
+                    symbols.push(symbol.clone());
+                }
+            }
+        }
+        symbols
+    }
+
+    pub fn remove(&mut self, token: &TokenValue, fixity: Fixity) -> &mut Self {
+        self.operators
+            .retain(|op| !(op.token == *token && op.fixity == fixity));
+        self
+    }
+
+    /// The operator `token` is when it starts an operand.
+    pub fn prefix(&self, token: &TokenValue) -> Option<&Operator> {
+        self.find(|op| op.token == *token && op.fixity == Fixity::Prefix)
+    }
+
+    /// The operator `token` is when it sits between two operands.
+    pub fn infix(&self, token: &TokenValue) -> Option<&Operator> {
+        self.find(|op| op.token == *token && op.fixity == Fixity::Infix)
+    }
+
+    /// The operator `token` is when it ends an operand.
+    pub fn postfix(&self, token: &TokenValue) -> Option<&Operator> {
+        self.find(|op| op.token == *token && op.fixity == Fixity::Postfix)
+    }
+
+    /// The operator a token already classified by the parser stands for.
+    pub fn operation(&self, value: &TokenValue) -> Option<&Operator> {
+        self.find(|op| op.value == *value)
+    }
+
+    fn find(&self, pred: impl Fn(&Operator) -> bool) -> Option<&Operator> {
+        self.operators.iter().find(|op| pred(op))
+    }
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        Self::c_like()
+    }
+}
@@ -1,20 +1,138 @@
-use crate::token::{Assoc, Token, TokenValue};
+use crate::ast::{Expr, ExprKind};
+use crate::operators::{Assoc, Fixity, Operator, OperatorTable};
+use crate::registry::{qualify, Arity, Registry, Symbol};
+use crate::token::{Loc, Span, Token, TokenValue};
+use crate::trace::{Place, Rule, State, Step, Trace};
 use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnbalancedLeftParen,
+    UnbalancedRightParen,
+    UnbalancedLeftBracket,
+    UnbalancedRightBracket,
+    UnbalancedQuestion,
+    UnbalancedColon,
+    UnknownIdentifier,
+    UnexpectedKeyword,
+    UnexpectedToken,
+    ExpectedOperand,
+    ExpectedOperator,
+    MisplacedPrefixOperator,
+    MissingOperand,
+    ExpectedLeftParen,
+    ExpectedMemberName,
+    IncompletePath,
+    UnexpectedComma,
+    ArityMismatch { expected: usize, found: usize },
+    InvalidAssignmentTarget,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub token: Token,
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        self.token.span()
+    }
+
+    pub fn start_loc(&self) -> Loc {
+        self.token.start_loc()
+    }
+
+    pub fn end_loc(&self) -> Loc {
+        self.token.end_loc()
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnbalancedLeftParen => write!(f, "No matching ')' for"),
+            Self::UnbalancedRightParen => write!(f, "No matching '(' for"),
+            Self::UnbalancedLeftBracket => write!(f, "No matching ']' for"),
+            Self::UnbalancedRightBracket => write!(f, "No matching '[' for"),
+            Self::UnbalancedQuestion => write!(f, "No matching ':' for"),
+            Self::UnbalancedColon => write!(f, "No matching '?' for"),
+            Self::UnknownIdentifier => write!(f, "Unknown identifier"),
+            Self::UnexpectedKeyword => write!(f, "Unexpected keyword"),
+            Self::UnexpectedToken => write!(f, "Unexpected token"),
+            Self::ExpectedOperand => write!(f, "Expected an operand but found"),
+            Self::ExpectedOperator => write!(f, "Expected an operator but found"),
+            Self::MisplacedPrefixOperator => {
+                write!(f, "Prefix operator cannot follow an operand:")
+            }
+            Self::MissingOperand => write!(f, "Missing operand after"),
+            Self::ExpectedLeftParen => write!(f, "Expected '(' after a function name but found"),
+            Self::IncompletePath => {
+                write!(f, "Expected a name after '::' in the path starting with")
+            }
+            Self::ExpectedMemberName => write!(f, "Expected a member name after '.' but found"),
+            Self::UnexpectedComma => write!(f, "Comma outside of a function call:"),
+            Self::InvalidAssignmentTarget => {
+                write!(f, "Left side is not a binding that can be assigned with")
+            }
+            Self::ArityMismatch { expected, found } => write!(
+                f,
+                "Expected {} argument(s) but found {} in call to",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = self.start_loc();
+        write!(
+            f,
+            "{} '{}' at {}:{}",
+            self.kind, self.token, loc.line, loc.col
+        )
+    }
+}
+
+impl Error for ParseError {}
 
 pub struct ShuntingYardParser {
     input_queue: VecDeque<Token>,
     operator_stack: VecDeque<Token>,
     pub output_queue: VecDeque<Token>,
+    trace: Trace,
+    /// Subtrees for the operands currently in the output queue, reduced each time
+    /// an operator or function reaches the output.
+    ast_stack: Vec<Expr>,
+    /// Height of `ast_stack` when each open function call started.
+    call_marks: Vec<usize>,
+    /// End of the last `)` or `]`, where the call or subscript it closes ends.
+    last_closing: Option<Span>,
 }
 
 impl ShuntingYardParser {
-    pub fn new(input_queue: Vec<Token>) -> Self {
+    /// Runs the algorithm over `input_queue`. A failure does not abort construction:
+    /// it is recorded as the final step of the trace and reported by [`Self::error`].
+    pub fn new(input_queue: Vec<Token>, registry: &Registry, operators: &OperatorTable) -> Self {
+        let input_queue = VecDeque::from(input_queue);
         let mut syp = Self {
-            input_queue: VecDeque::from(input_queue),
+            trace: Trace::new(State {
+                input_queue: input_queue.clone(),
+                ..State::default()
+            }),
+            input_queue,
             operator_stack: VecDeque::new(),
             output_queue: VecDeque::new(),
+            ast_stack: Vec::new(),
+            call_marks: Vec::new(),
+            last_closing: None,
         };
-        syp.parse();
+        if let Err(e) = syp.parse(registry, operators) {
+            syp.trace.fail(e);
+        }
         syp
     }
 
@@ -24,89 +142,831 @@ impl ShuntingYardParser {
         }
     }
 
-    fn parse(&mut self) {
-        let known_functions = vec![String::from("max"), String::from("sin")];
-        let known_bindings = vec![String::from("x"), String::from("y")];
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn error(&self) -> Option<&ParseError> {
+        self.trace.error()
+    }
+
+    /// The expression tree, available when parsing succeeded on a non-empty input.
+    pub fn ast(&self) -> Option<&Expr> {
+        match self.ast_stack.as_slice() {
+            [expr] if self.error().is_none() => Some(expr),
+            _ => None,
+        }
+    }
+
+    fn parse(&mut self, registry: &Registry, operators: &OperatorTable) -> Result<(), ParseError> {
+        // One entry per unclosed '(': `Some(commas seen)` for a call's argument
+        // list, `None` for a plain group.
+        let mut open_parens: Vec<Option<usize>> = Vec::new();
+        let mut after_function = false;
+        let mut after_dot = false;
+
+        // Whether the next token must start an operand (a value, a function call,
+        // a parenthesized group or a prefix operator applied to one of those) or
+        // must continue one with an infix operator, `,` or `)`. This is what tells
+        // a prefix `-x` apart from an infix `a - x`.
+        let mut expect_operand = true;
+
+        while let Some(mut t) = self.input_queue.front().cloned() {
+            // `a::b` is one qualified name, joined before anything else sees it
+            if let (TokenValue::Identifier(name), Some(TokenValue::ColonColon)) =
+                (&t.value, self.input_queue.get(1).map(|next| &next.value))
+            {
+                let Some(TokenValue::Identifier(segment)) =
+                    self.input_queue.get(2).map(|next| next.value.clone())
+                else {
+                    return Err(self.reject(Place::InputQueue, ParseErrorKind::IncompletePath));
+                };
+                let span = t.span().to(self.input_queue[2].span());
+                let path = TokenValue::Identifier(qualify(name, &segment));
+                self.rewrite_input(3, vec![Token::new(span, path)], Rule::JoinPath);
+                continue;
+            }
+
+            // `++` and `--` are lexed as one token, but where the table gives them
+            // no reading that fits, `2--1` is `2 - -1` and `--1` is `-(-1)`. A
+            // postfix increment only fits a binding.
+            if let Some(single) = match t.value {
+                TokenValue::PlusPlus => Some(TokenValue::Plus),
+                TokenValue::MinusMinus => Some(TokenValue::Minus),
+                _ => None,
+            } {
+                let fits = match expect_operand {
+                    true => operators.prefix(&t.value).is_some(),
+                    false => {
+                        operators.infix(&t.value).is_some()
+                            || operators.postfix(&t.value).is_some_and(|op| {
+                                !op.value.is_increment()
+                                    || matches!(
+                                        self.ast_stack.last(),
+                                        Some(Expr {
+                                            kind: ExprKind::Identifier(_),
+                                            ..
+                                        })
+                                    )
+                            })
+                    }
+                };
+                if !fits {
+                    let tokens = split_token(&t, single);
+                    self.rewrite_input(1, tokens.to_vec(), Rule::SplitOperator);
+                    continue;
+                }
+            }
+
+            // A token that is both a prefix and an infix operator, like `-`, is
+            // read as whichever fits the position. One that is both postfix and
+            // infix is postfix only when nothing that could start a right operand
+            // follows. The token is then replaced by the operation it stands for.
+            let prefix = operators.prefix(&t.value);
+            let infix = operators.infix(&t.value);
+            let postfix = operators.postfix(&t.value).filter(|_| {
+                infix.is_none()
+                    || !self
+                        .input_queue
+                        .get(1)
+                        .is_some_and(|next| can_start_operand(&next.value, operators))
+            });
+            let operator = match expect_operand {
+                true => prefix.or(infix).or(postfix),
+                false => postfix.or(infix).or(prefix),
+            }
+            .cloned();
+            // A '?' or '[' is only resolved once its ':' or ']' is found
+            if let Some(op) = operator
+                .as_ref()
+                .filter(|op| !matches!(op.value, TokenValue::Ternary | TokenValue::Index(_)))
+            {
+                t.value = op.value.clone();
+                self.input_queue[0].value = op.value.clone();
+            }
+            let fixity = operator.as_ref().map(|op| op.fixity);
+
+            // A name after '.' is a member of the operand before it, not something
+            // the registry knows about
+            let member = after_dot && matches!(t.value, TokenValue::Identifier(_));
+            let symbol = match &t.value {
+                TokenValue::Identifier(_) if member => None,
+                TokenValue::Identifier(name) => {
+                    let before_paren = self
+                        .input_queue
+                        .get(1)
+                        .is_some_and(|next| next.value == TokenValue::LeftParen);
+                    registry.lookup(name, before_paren)
+                }
+                _ => None,
+            };
+            let starts_operand = fixity == Some(Fixity::Prefix)
+                || member
+                || symbol.is_some()
+                || t.value.is_literal()
+                || t.value == TokenValue::LeftParen;
+            let continues_operand = matches!(fixity, Some(Fixity::Infix | Fixity::Postfix))
+                || matches!(
+                    t.value,
+                    TokenValue::Comma
+                        | TokenValue::RightParen
+                        | TokenValue::RightBracket
+                        | TokenValue::Colon
+                );
+            let empty_call = expect_operand
+                && t.value == TokenValue::RightParen
+                && self
+                    .operator_stack
+                    .front()
+                    .is_some_and(|top| top.value == TokenValue::LeftParen)
+                && open_parens.last() == Some(&Some(0));
+            if after_function && t.value != TokenValue::LeftParen {
+                return Err(self.reject(Place::InputQueue, ParseErrorKind::ExpectedLeftParen));
+            }
+            if after_dot && !member {
+                return Err(self.reject(Place::InputQueue, ParseErrorKind::ExpectedMemberName));
+            }
+            after_dot = false;
+            if expect_operand && continues_operand && !empty_call {
+                return Err(self.reject(Place::InputQueue, ParseErrorKind::ExpectedOperand));
+            }
+            if !expect_operand && starts_operand {
+                let kind = if fixity == Some(Fixity::Prefix) {
+                    ParseErrorKind::MisplacedPrefixOperator
+                } else {
+                    ParseErrorKind::ExpectedOperator
+                };
+                return Err(self.reject(Place::InputQueue, kind));
+            }
 
-        while let Some(t) = self.input_queue.pop_front() {
-            // self.debug_stack();
             match &t.value {
-                TokenValue::Integer(_) | TokenValue::Float(_) => self.output_queue.push_back(t),
-                _variable if t.value.is_binding(&known_bindings) => self.output_queue.push_back(t),
-                _function if t.value.is_func(&known_functions) => self.operator_stack.push_front(t),
-                _operator if t.value.is_op() => {
+                TokenValue::Identifier(name) if member => {
+                    let before_paren = self
+                        .input_queue
+                        .get(1)
+                        .is_some_and(|next| next.value == TokenValue::LeftParen);
+                    if before_paren {
+                        // A method: the receiver already output becomes its first
+                        // argument, so the '.' has nothing left to do
+                        let Some(dot) = self.operator_stack.pop_front() else {
+                            unreachable!()
+                        };
+                        self.move_token(dot, Place::OperatorStack, Place::Discarded, Rule::Method);
+                        self.call_marks.push(self.ast_stack.len() - 1);
+                        self.input_queue[0].value = TokenValue::Method(name.clone(), 0);
+                        self.shift(Place::OperatorStack, Rule::Function);
+                        after_function = true;
+                        continue;
+                    }
+                    self.input_queue[0].value = TokenValue::Field(name.clone());
+                    self.shift(Place::OutputQueue, Rule::Member);
+                    // Nothing can come between a '.' and its member, so a postfix
+                    // or infix operator binding tighter than '.' must apply to
+                    // `a.b` rather than to `b`
+                    self.pop_operator(Rule::PopMember);
+                    expect_operand = false;
+                }
+                _literal if t.value.is_literal() => {
+                    self.shift(Place::OutputQueue, Rule::Operand);
+                    expect_operand = false;
+                }
+                _variable if symbol == Some(Symbol::Binding) => {
+                    self.shift(Place::OutputQueue, Rule::Operand);
+                    expect_operand = false;
+                }
+                _function if matches!(symbol, Some(Symbol::Function(_))) => {
+                    self.call_marks.push(self.ast_stack.len());
+                    self.shift(Place::OperatorStack, Rule::Function);
+                    after_function = true;
+                    continue;
+                }
+                TokenValue::LeftParen => {
+                    open_parens.push(after_function.then_some(0));
+                    self.shift(Place::OperatorStack, Rule::LeftParen);
+                }
+                _prefix if fixity == Some(Fixity::Prefix) => {
+                    self.shift(Place::OperatorStack, Rule::PrefixOperator);
+                }
+                TokenValue::LeftBracket if fixity == Some(Fixity::Postfix) => {
+                    let Some(op) = &operator else { unreachable!() };
+                    self.pop_higher_precedence(op, operators);
+                    // Like a call whose first argument is the operand just output
+                    self.call_marks.push(self.ast_stack.len() - 1);
+                    open_parens.push(Some(0));
+                    self.shift(Place::OperatorStack, Rule::LeftBracket);
+                    expect_operand = true;
+                }
+                TokenValue::RightBracket => {
                     while let Some(next_t) = self.operator_stack.front() {
-                        if next_t.value.is_op()
-                            && next_t.value != TokenValue::LeftParen
-                            && (next_t.value.precedence() > t.value.precedence()
-                                || (t.value.precedence() == next_t.value.precedence()
-                                    && t.value.assoc().unwrap() == Assoc::Left))
-                        {
-                            let Some(next_t) = self.operator_stack.pop_front() else { todo!() };
-                            self.output_queue.push_back(next_t);
+                        if operators.operation(&next_t.value).is_some() {
+                            self.pop_operator(Rule::PopUntilLeftBracket);
                         } else {
                             break;
                         }
                     }
-                    self.operator_stack.push_front(t);
+                    self.check_no_open(TokenValue::Question)?;
+                    self.check_no_open(TokenValue::LeftParen)?;
+                    if !self
+                        .operator_stack
+                        .front()
+                        .is_some_and(|t| t.value == TokenValue::LeftBracket)
+                    {
+                        return Err(
+                            self.reject(Place::InputQueue, ParseErrorKind::UnbalancedRightBracket)
+                        );
+                    }
+                    let Some(Some(commas)) = open_parens.pop() else {
+                        unreachable!()
+                    };
+                    self.last_closing = Some(t.span());
+                    self.shift(Place::Discarded, Rule::RightBracket);
+                    // The subscripted operand and one index per comma-separated item
+                    self.operator_stack[0].value = TokenValue::Index(commas + 2);
+                    self.pop_operator(Rule::PopIndex);
+                    expect_operand = false;
                 }
-                TokenValue::Comma => {
+                _postfix if fixity == Some(Fixity::Postfix) => {
+                    let Some(op) = &operator else { unreachable!() };
+                    self.pop_higher_precedence(op, operators);
+                    if t.value.is_increment() {
+                        self.bind_target()?;
+                    }
+                    self.shift(Place::OutputQueue, Rule::PostfixOperator);
+                }
+                TokenValue::Question if fixity == Some(Fixity::Infix) => {
+                    let Some(op) = &operator else { unreachable!() };
+                    self.pop_higher_precedence(op, operators);
+                    // The condition is complete: the '?' in the output queue lets
+                    // evaluation skip the branch that is not taken
+                    let branch = t.clone();
+                    self.shift(Place::OutputQueue, Rule::Branch);
+                    // Unresolved, the '?' has no precedence of its own, so nothing
+                    // in the middle operand can pop it
+                    self.move_token(
+                        branch,
+                        Place::InputQueue,
+                        Place::OperatorStack,
+                        Rule::Question,
+                    );
+                    expect_operand = true;
+                }
+                TokenValue::Colon => {
                     while let Some(next_t) = self.operator_stack.front() {
-                        if next_t.value.is_op() && next_t.value != TokenValue::LeftParen {
-                            let Some(next_t) = self.operator_stack.pop_front() else { todo!() };
-                            self.output_queue.push_back(next_t);
+                        if operators.operation(&next_t.value).is_some() {
+                            self.pop_operator(Rule::PopUntilQuestion);
                         } else {
                             break;
                         }
                     }
+                    if !self
+                        .operator_stack
+                        .front()
+                        .is_some_and(|t| t.value == TokenValue::Question)
+                    {
+                        return Err(self.reject(Place::InputQueue, ParseErrorKind::UnbalancedColon));
+                    }
+                    self.operator_stack[0].value = TokenValue::Ternary;
+                    self.shift(Place::OutputQueue, Rule::Colon);
+                    expect_operand = true;
                 }
-                TokenValue::LeftParen => {
-                    self.operator_stack.push_front(t);
+                _operator if fixity == Some(Fixity::Infix) => {
+                    let Some(op) = &operator else { unreachable!() };
+                    self.pop_higher_precedence(op, operators);
+                    if t.value.is_assignment() {
+                        // Assignment binds loosest, so its whole left side has been
+                        // reduced by now
+                        self.bind_target()?;
+                    }
+                    self.shift(Place::OperatorStack, Rule::Operator);
+                    expect_operand = true;
+                    if t.value == TokenValue::Dot {
+                        after_dot = true;
+                        continue;
+                    }
                 }
-                TokenValue::RightParen => {
+                TokenValue::Comma => {
                     while let Some(next_t) = self.operator_stack.front() {
-                        if next_t.value.is_op() && next_t.value != TokenValue::LeftParen {
-                            let Some(next_t) = self.operator_stack.pop_front() else { todo!() };
-                            self.output_queue.push_back(next_t);
+                        if operators.operation(&next_t.value).is_some() {
+                            self.pop_operator(Rule::PopUntilComma);
                         } else {
                             break;
                         }
                     }
-                    if self.operator_stack.is_empty() {
-                        panic!("Mismatched parentheses!");
+                    self.check_no_open(TokenValue::Question)?;
+                    match open_parens.last_mut() {
+                        Some(Some(commas)) => *commas += 1,
+                        _ => {
+                            return Err(
+                                self.reject(Place::InputQueue, ParseErrorKind::UnexpectedComma)
+                            )
+                        }
                     }
-                    assert!(self
+                    self.shift(Place::Discarded, Rule::Comma);
+                    expect_operand = true;
+                }
+                TokenValue::RightParen => {
+                    while let Some(next_t) = self.operator_stack.front() {
+                        if operators.operation(&next_t.value).is_some() {
+                            self.pop_operator(Rule::PopUntilLeftParen);
+                        } else {
+                            break;
+                        }
+                    }
+                    self.check_no_open(TokenValue::Question)?;
+                    self.check_no_open(TokenValue::LeftBracket)?;
+                    if !self
                         .operator_stack
                         .front()
-                        .is_some_and(|t| { t.value == TokenValue::LeftParen }));
-                    self.operator_stack.pop_front(); // pop '('
-                    if let Some(next_t) = self.operator_stack.front() {
-                        if next_t.value.is_func(&known_functions) {
-                            let Some(next_t) = self.operator_stack.pop_front() else { todo!() };
-                            self.output_queue.push_back(next_t);
+                        .is_some_and(|t| t.value == TokenValue::LeftParen)
+                    {
+                        return Err(
+                            self.reject(Place::InputQueue, ParseErrorKind::UnbalancedRightParen)
+                        );
+                    }
+                    let Some(paren) = self.operator_stack.pop_front() else {
+                        unreachable!()
+                    };
+                    self.move_token(
+                        paren,
+                        Place::OperatorStack,
+                        Place::Discarded,
+                        Rule::DiscardLeftParen,
+                    );
+                    self.last_closing = Some(t.span());
+                    self.shift(Place::Discarded, Rule::RightParen);
+                    if let Some(Some(commas)) = open_parens.pop() {
+                        let mut arity = if empty_call { 0 } else { commas + 1 };
+                        let (name, method) =
+                            match self.operator_stack.front().map(|f| f.value.clone()) {
+                                Some(TokenValue::Identifier(name)) => (name, false),
+                                Some(TokenValue::Method(name, _)) => (name, true),
+                                _ => unreachable!(),
+                            };
+                        if method {
+                            arity += 1;
                         }
+                        if let Some(Arity::Fixed(expected)) = registry.function_arity(&name) {
+                            if expected != arity {
+                                return Err(self.reject(
+                                    Place::OperatorStack,
+                                    ParseErrorKind::ArityMismatch {
+                                        expected,
+                                        found: arity,
+                                    },
+                                ));
+                            }
+                        }
+                        self.operator_stack[0].value = match method {
+                            true => TokenValue::Method(name, arity),
+                            false => TokenValue::Call(name, arity),
+                        };
+                        self.pop_operator(Rule::PopFunction);
                     }
+                    expect_operand = false;
+                }
+                TokenValue::Identifier(_) => {
+                    return Err(self.reject(Place::InputQueue, ParseErrorKind::UnknownIdentifier));
+                }
+                _keyword if t.value.is_keyword() => {
+                    return Err(self.reject(Place::InputQueue, ParseErrorKind::UnexpectedKeyword));
                 }
-                _ => todo!(),
+                _ => return Err(self.reject(Place::InputQueue, ParseErrorKind::UnexpectedToken)),
             }
+            after_function = false;
         }
-        while let Some(t) = self.operator_stack.pop_front() {
+        if expect_operand && !self.operator_stack.is_empty() {
+            return Err(self.reject(Place::OperatorStack, ParseErrorKind::MissingOperand));
+        }
+        while let Some(t) = self.operator_stack.front() {
             match t.value {
                 TokenValue::LeftParen => {
-                    panic!("Mismatched parentheses!");
+                    return Err(
+                        self.reject(Place::OperatorStack, ParseErrorKind::UnbalancedLeftParen)
+                    );
+                }
+                TokenValue::Question => {
+                    return Err(
+                        self.reject(Place::OperatorStack, ParseErrorKind::UnbalancedQuestion)
+                    );
+                }
+                TokenValue::LeftBracket => {
+                    return Err(
+                        self.reject(Place::OperatorStack, ParseErrorKind::UnbalancedLeftBracket)
+                    );
                 }
                 _ => {
-                    self.output_queue.push_back(t);
+                    self.pop_operator(Rule::Drain);
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Fails if `opener` is on top of the operator stack, as when `,`, `)` or `]`
+    /// ends the operand of a `?` still waiting for its `:`, or closes across an
+    /// unmatched bracket of the other kind.
+    fn check_no_open(&mut self, opener: TokenValue) -> Result<(), ParseError> {
+        let kind = match opener {
+            TokenValue::LeftParen => ParseErrorKind::UnbalancedLeftParen,
+            TokenValue::LeftBracket => ParseErrorKind::UnbalancedLeftBracket,
+            TokenValue::Question => ParseErrorKind::UnbalancedQuestion,
+            _ => unreachable!(),
+        };
+        if self
+            .operator_stack
+            .front()
+            .is_some_and(|t| t.value == opener)
+        {
+            return Err(self.reject(Place::OperatorStack, kind));
+        }
+        Ok(())
+    }
+
+    /// Pops the operators that bind tighter than `op` into the output queue, as
+    /// done before `op` is placed.
+    fn pop_higher_precedence(&mut self, op: &Operator, operators: &OperatorTable) {
+        while let Some(next_t) = self.operator_stack.front() {
+            let Some(top) = operators.operation(&next_t.value) else {
+                break;
+            };
+            if top.precedence > op.precedence
+                || (top.precedence == op.precedence && op.assoc == Assoc::Left)
+            {
+                self.pop_operator(Rule::PopHigherPrecedence);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Marks the operand just output as the binding the operator at the front of
+    /// the input queue writes to. Only a lone binding can be written to, and it
+    /// is then the last token output.
+    fn bind_target(&mut self) -> Result<(), ParseError> {
+        let Some(Expr {
+            kind: ExprKind::Identifier(name),
+            ..
+        }) = self.ast_stack.last()
+        else {
+            return Err(self.reject(Place::InputQueue, ParseErrorKind::InvalidAssignmentTarget));
+        };
+        let target = TokenValue::Target(name.clone());
+        let Some(last) = self.output_queue.back_mut() else {
+            unreachable!()
+        };
+        last.value = target;
+        let t = last.clone();
+        self.record(t, Place::OutputQueue, Place::OutputQueue, Rule::BindTarget);
+        Ok(())
+    }
+
+    /// Takes the next token from the input queue and places it in `to`.
+    fn shift(&mut self, to: Place, rule: Rule) {
+        let Some(t) = self.input_queue.pop_front() else {
+            unreachable!()
+        };
+        self.move_token(t, Place::InputQueue, to, rule);
+    }
+
+    /// Pops the top of the operator stack into the output queue.
+    fn pop_operator(&mut self, rule: Rule) {
+        let Some(t) = self.operator_stack.pop_front() else {
+            unreachable!()
+        };
+        self.move_token(t, Place::OperatorStack, Place::OutputQueue, rule);
+    }
+
+    /// Moves the offending token at the front of `from` out of the algorithm and
+    /// builds the error describing why.
+    fn reject(&mut self, from: Place, kind: ParseErrorKind) -> ParseError {
+        let t = match from {
+            Place::InputQueue => self.input_queue.pop_front(),
+            Place::OperatorStack => self.operator_stack.pop_front(),
+            _ => unreachable!(),
+        };
+        let Some(t) = t else { unreachable!() };
+        self.move_token(t.clone(), from, Place::Rejected, Rule::Fail);
+        ParseError { kind, token: t }
+    }
+
+    /// Replaces the first `count` tokens of the input queue with `tokens`,
+    /// recording the rewrite as a step on the first of them.
+    fn rewrite_input(&mut self, count: usize, tokens: Vec<Token>, rule: Rule) {
+        self.input_queue.drain(..count);
+        for t in tokens.iter().rev() {
+            self.input_queue.push_front(t.clone());
+        }
+        self.record(
+            tokens[0].clone(),
+            Place::InputQueue,
+            Place::InputQueue,
+            rule,
+        );
     }
 
-    fn debug_stack(&self) {
-        print!("[");
-        for op_func in &self.operator_stack {
-            print!("{:?}, ", op_func.value);
+    /// Places a token that has already been taken from `from` into `to`,
+    /// recording the move in the trace.
+    fn move_token(&mut self, t: Token, from: Place, to: Place, rule: Rule) {
+        match to {
+            Place::InputQueue => self.input_queue.push_front(t.clone()),
+            Place::OperatorStack => self.operator_stack.push_front(t.clone()),
+            Place::OutputQueue => {
+                self.reduce(&t);
+                self.output_queue.push_back(t.clone());
+            }
+            Place::Discarded | Place::Rejected => (),
         }
-        println!("]");
+        self.record(t, from, to, rule);
+    }
+
+    fn record(&mut self, t: Token, from: Place, to: Place, rule: Rule) {
+        self.trace.push(Step {
+            rule,
+            token: t,
+            from,
+            to,
+            state: State {
+                input_queue: self.input_queue.clone(),
+                operator_stack: self.operator_stack.clone(),
+                output_queue: self.output_queue.clone(),
+            },
+        });
+    }
+
+    /// Builds the subtree for a token entering the output queue from the
+    /// subtrees of the operands already there.
+    fn reduce(&mut self, t: &Token) {
+        let span = t.span();
+        let expr = match &t.value {
+            // Jumps between the branches of a conditional, not operations
+            TokenValue::Question | TokenValue::Colon => return,
+            TokenValue::Integer(i) => Expr::new(ExprKind::Integer(*i), span),
+            TokenValue::BigInteger(i) => Expr::new(ExprKind::BigInteger(i.clone()), span),
+            TokenValue::Float(x) => Expr::new(ExprKind::Float(*x), span),
+            TokenValue::String(str) => Expr::new(ExprKind::String(str.clone()), span),
+            TokenValue::True => Expr::new(ExprKind::Bool(true), span),
+            TokenValue::False => Expr::new(ExprKind::Bool(false), span),
+            TokenValue::Null => Expr::new(ExprKind::Null, span),
+            TokenValue::Identifier(name) => Expr::new(ExprKind::Identifier(name.clone()), span),
+            TokenValue::Field(name) => Expr::new(ExprKind::Identifier(name.clone()), span),
+            TokenValue::Method(name, _) => {
+                let Some(mark) = self.call_marks.pop() else {
+                    unreachable!()
+                };
+                let mut args = self.ast_stack.split_off(mark);
+                let receiver = Box::new(args.remove(0));
+                let span = receiver.span.to(self.last_closing.unwrap_or(span));
+                let name = name.clone();
+                let kind = ExprKind::MethodCall {
+                    receiver,
+                    name,
+                    args,
+                };
+                Expr::new(kind, span)
+            }
+            TokenValue::Index(_) => {
+                let Some(mark) = self.call_marks.pop() else {
+                    unreachable!()
+                };
+                let mut indices = self.ast_stack.split_off(mark);
+                let object = Box::new(indices.remove(0));
+                let span = object.span.to(self.last_closing.unwrap_or(span));
+                Expr::new(ExprKind::Index { object, indices }, span)
+            }
+            TokenValue::Dot => {
+                let (Some(rhs), Some(object)) = (self.ast_stack.pop(), self.ast_stack.pop()) else {
+                    unreachable!()
+                };
+                let ExprKind::Identifier(name) = rhs.kind else {
+                    unreachable!()
+                };
+                let span = object.span.to(rhs.span);
+                let object = Box::new(object);
+                Expr::new(ExprKind::Member { object, name }, span)
+            }
+            TokenValue::Call(name, _) => {
+                let Some(mark) = self.call_marks.pop() else {
+                    unreachable!()
+                };
+                let args = self.ast_stack.split_off(mark);
+                let span = span.to(self.last_closing.unwrap_or(span));
+                let name = name.clone();
+                Expr::new(ExprKind::Call { name, args }, span)
+            }
+            op if op.is_prefix_op() => {
+                let Some(operand) = self.ast_stack.pop() else {
+                    unreachable!()
+                };
+                let span = span.to(operand.span);
+                let operand = Box::new(operand);
+                Expr::new(
+                    ExprKind::Unary {
+                        op: op.clone(),
+                        operand,
+                    },
+                    span,
+                )
+            }
+            TokenValue::Ternary => {
+                let (Some(otherwise), Some(then), Some(condition)) = (
+                    self.ast_stack.pop(),
+                    self.ast_stack.pop(),
+                    self.ast_stack.pop(),
+                ) else {
+                    unreachable!()
+                };
+                let span = condition.span.to(otherwise.span);
+                let kind = ExprKind::Conditional {
+                    condition: Box::new(condition),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                };
+                Expr::new(kind, span)
+            }
+            op if op.is_postfix_op() => {
+                let Some(operand) = self.ast_stack.pop() else {
+                    unreachable!()
+                };
+                let span = operand.span.to(span);
+                let operand = Box::new(operand);
+                Expr::new(
+                    ExprKind::Postfix {
+                        op: op.clone(),
+                        operand,
+                    },
+                    span,
+                )
+            }
+            op => {
+                let (Some(rhs), Some(lhs)) = (self.ast_stack.pop(), self.ast_stack.pop()) else {
+                    unreachable!()
+                };
+                let span = lhs.span.to(rhs.span);
+                let (op, lhs, rhs) = (op.clone(), Box::new(lhs), Box::new(rhs));
+                let kind = if op.is_assignment() {
+                    ExprKind::Assign {
+                        op,
+                        target: lhs,
+                        value: rhs,
+                    }
+                } else {
+                    ExprKind::Binary { op, lhs, rhs }
+                };
+                Expr::new(kind, span)
+            }
+        };
+        self.ast_stack.push(expr);
+    }
+}
+
+/// Splits a two-character operator such as `--` into two tokens of `single`,
+/// each spanning one character.
+fn split_token(t: &Token, single: TokenValue) -> [Token; 2] {
+    let span = t.span();
+    let middle = Loc::new(span.start_loc.line, span.start_loc.col + 1);
+    [
+        Token::new(
+            Span::new(span.start, span.start + 1, span.start_loc, middle),
+            single.clone(),
+        ),
+        Token::new(
+            Span::new(span.start + 1, span.end, middle, span.end_loc),
+            single,
+        ),
+    ]
+}
+
+/// Whether a token could be the first token of an operand.
+fn can_start_operand(value: &TokenValue, operators: &OperatorTable) -> bool {
+    value.is_literal()
+        || matches!(value, TokenValue::Identifier(_) | TokenValue::LeftParen)
+        || operators.prefix(value).is_some()
+}
+
+/// A sequence of `;`-separated statements. Each statement is parsed on its own,
+/// starting from an empty operator stack, and has its own output queue and trace.
+pub struct Program {
+    statements: Vec<ShuntingYardParser>,
+}
+
+impl Program {
+    /// Parses statements in order, stopping after the first one that fails.
+    /// Empty statements, as left by a trailing `;`, are skipped.
+    pub fn new(tokens: Vec<Token>, registry: &Registry, operators: &OperatorTable) -> Self {
+        let mut statements = Vec::new();
+        for tokens in tokens.split(|t| t.value == TokenValue::Semicolon) {
+            if tokens.is_empty() {
+                continue;
+            }
+            let statement = ShuntingYardParser::new(tokens.to_vec(), registry, operators);
+            let failed = statement.error().is_some();
+            statements.push(statement);
+            if failed {
+                break;
+            }
+        }
+        if statements.is_empty() {
+            statements.push(ShuntingYardParser::new(Vec::new(), registry, operators));
+        }
+        Self { statements }
+    }
+
+    pub fn statements(&self) -> &[ShuntingYardParser] {
+        &self.statements
+    }
+
+    pub fn error(&self) -> Option<&ParseError> {
+        self.statements.last().and_then(|s| s.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerConfig};
+
+    fn parse(source: &str, operators: &OperatorTable) -> ShuntingYardParser {
+        let config = LexerConfig {
+            operators: operators.symbols(),
+            ..LexerConfig::default()
+        };
+        let lexer = Lexer::with_config(source.to_string(), config).unwrap();
+        ShuntingYardParser::new(lexer.tokens, &Registry::default(), operators)
+    }
+
+    fn rpn(parser: &ShuntingYardParser) -> String {
+        let tokens: Vec<String> = parser.output_queue.iter().map(|t| t.to_string()).collect();
+        tokens.join(" ")
+    }
+
+    #[test]
+    fn member_is_taken_before_tighter_operators() {
+        let mut operators = OperatorTable::default();
+        operators
+            .declare("%%", Fixity::Postfix, 20, Assoc::Left)
+            .declare("@@", Fixity::Infix, 20, Assoc::Left);
+
+        let parser = parse("a.b%%", &operators);
+        assert_eq!(parser.error(), None);
+        assert_eq!(rpn(&parser), "a b . %%");
+
+        let parser = parse("a.b @@ c", &operators);
+        assert_eq!(parser.error(), None);
+        assert_eq!(rpn(&parser), "a b . c @@");
+        assert_eq!(parser.ast().unwrap().to_string(), "(a.b @@ c)");
+    }
+
+    #[test]
+    fn double_minus_is_split_where_no_decrement_fits() {
+        for operators in [OperatorTable::c_like(), OperatorTable::math()] {
+            let parser = parse("2--1", &operators);
+            assert_eq!(parser.error(), None);
+            assert_eq!(rpn(&parser), "2 1 u- -");
+
+            let parser = parse("--1", &operators);
+            assert_eq!(parser.error(), None);
+            assert_eq!(rpn(&parser), "1 u- u-");
+        }
+
+        let parser = parse("x--", &OperatorTable::c_like());
+        assert_eq!(parser.error(), None);
+        assert_eq!(rpn(&parser), "&x --");
+    }
+
+    fn rules(parser: &ShuntingYardParser) -> Vec<Rule> {
+        parser.trace().steps().iter().map(|s| s.rule).collect()
+    }
+
+    #[test]
+    fn assignment_target_is_traced() {
+        let parser = parse("x = 1", &OperatorTable::default());
+        assert_eq!(parser.error(), None);
+        assert!(rules(&parser).contains(&Rule::BindTarget));
+    }
+
+    #[test]
+    fn path_join_is_traced() {
+        let parser = parse("math :: sin(1)", &OperatorTable::default());
+        assert_eq!(parser.error(), None);
+        assert!(rules(&parser).contains(&Rule::JoinPath));
+    }
+
+    #[test]
+    fn conditional_leaves_jumps_in_rpn() {
+        let operators = OperatorTable::default();
+        let parser = parse("c ? a + 1 : d ? 2 : 3", &operators);
+        assert_eq!(parser.error(), None);
+        assert_eq!(rpn(&parser), "c ? a 1 + : d ? 2 : 3 ?: ?:");
+        assert_eq!(
+            parser.ast().unwrap().to_string(),
+            "(c ? (a + 1) : (d ? 2 : 3))"
+        );
+    }
+
+    #[test]
+    fn math_notation_compares_for_equality() {
+        let parser = parse("2^2 == 4 != 1 < 2", &OperatorTable::math());
+        assert_eq!(parser.error(), None);
+        assert_eq!(rpn(&parser), "2 2 ** 4 == 1 2 < !=");
     }
 }
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Fixed(usize),
+    Variadic,
+}
+
+pub const BUILTIN_FUNCTIONS: [(&str, Arity); 13] = [
+    ("max", Arity::Variadic),
+    ("min", Arity::Variadic),
+    ("abs", Arity::Fixed(1)),
+    ("sqrt", Arity::Fixed(1)),
+    ("exp", Arity::Fixed(1)),
+    ("ln", Arity::Fixed(1)),
+    ("sin", Arity::Fixed(1)),
+    ("cos", Arity::Fixed(1)),
+    ("tan", Arity::Fixed(1)),
+    ("floor", Arity::Fixed(1)),
+    ("ceil", Arity::Fixed(1)),
+    ("round", Arity::Fixed(1)),
+    ("pow", Arity::Fixed(2)),
+];
+
+/// Namespace the builtin functions are also declared in, as in `math::sin`.
+pub const BUILTIN_NAMESPACE: &str = "math";
+
+pub const BUILTIN_CONSTANTS: [(&str, f64); 3] = [
+    ("consts::PI", std::f64::consts::PI),
+    ("consts::E", std::f64::consts::E),
+    ("consts::TAU", std::f64::consts::TAU),
+];
+
+/// What an identifier means to the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    Function(Arity),
+    Binding,
+}
+
+/// Functions and bindings the parser accepts as identifiers.
+///
+/// With inference on, an identifier that is not declared is taken to be a call
+/// when it is followed by `(` and a binding otherwise, so arbitrary expressions
+/// parse without declaring anything. Qualified names like `math::sin` are never
+/// inferred: a namespace only holds what was declared in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    functions: HashMap<String, Arity>,
+    bindings: HashSet<String>,
+    infer: bool,
+}
+
+impl Registry {
+    /// An empty registry that rejects every undeclared identifier.
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+            bindings: HashSet::new(),
+            infer: false,
+        }
+    }
+
+    /// A registry with [`BUILTIN_FUNCTIONS`] declared both plain and under
+    /// [`BUILTIN_NAMESPACE`], [`BUILTIN_CONSTANTS`] declared, and inference on.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        for (name, arity) in BUILTIN_FUNCTIONS {
+            registry.declare_function(name, arity);
+            registry.declare_function(&qualify(BUILTIN_NAMESPACE, name), arity);
+        }
+        for (name, _) in BUILTIN_CONSTANTS {
+            registry.declare_binding(name);
+        }
+        registry.set_infer(true);
+        registry
+    }
+
+    pub fn declare_function(&mut self, name: &str, arity: Arity) -> &mut Self {
+        self.bindings.remove(name);
+        self.functions.insert(name.to_string(), arity);
+        self
+    }
+
+    pub fn declare_binding(&mut self, name: &str) -> &mut Self {
+        self.functions.remove(name);
+        self.bindings.insert(name.to_string());
+        self
+    }
+
+    pub fn set_infer(&mut self, infer: bool) -> &mut Self {
+        self.infer = infer;
+        self
+    }
+
+    pub fn infers(&self) -> bool {
+        self.infer
+    }
+
+    pub fn function_arity(&self, name: &str) -> Option<Arity> {
+        self.functions.get(name).copied()
+    }
+
+    pub fn is_binding(&self, name: &str) -> bool {
+        self.bindings.contains(name)
+    }
+
+    /// Resolves `name`, using `before_paren` (whether the next token is `(`) to
+    /// infer undeclared identifiers.
+    pub fn lookup(&self, name: &str, before_paren: bool) -> Option<Symbol> {
+        if let Some(arity) = self.function_arity(name) {
+            Some(Symbol::Function(arity))
+        } else if self.is_binding(name) {
+            Some(Symbol::Binding)
+        } else if !self.infer || is_qualified(name) {
+            None
+        } else if before_paren {
+            Some(Symbol::Function(Arity::Variadic))
+        } else {
+            Some(Symbol::Binding)
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+/// Joins a namespace and a name into a path such as `math::sin`.
+pub fn qualify(namespace: &str, name: &str) -> String {
+    format!("{}::{}", namespace, name)
+}
+
+pub fn is_qualified(name: &str) -> bool {
+    name.contains("::")
+}
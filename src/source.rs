@@ -0,0 +1,46 @@
+use crate::ast::Expr;
+use crate::token::{Span, Token};
+
+/// Source text kept alongside what was parsed from it, so the text behind any
+/// token, AST node or trace step can be recovered from its [`Span`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    name: String,
+    text: String,
+}
+
+impl SourceFile {
+    /// `name` is only used to label diagnostics, e.g. a path or `<expression>`.
+    pub fn new(name: &str, text: String) -> Self {
+        Self {
+            name: name.to_string(),
+            text,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The text a span covers, or `None` when the span is not of this source.
+    pub fn slice(&self, span: Span) -> Option<&str> {
+        self.text.get(span.range())
+    }
+
+    pub fn token_text(&self, token: &Token) -> Option<&str> {
+        self.slice(token.span())
+    }
+
+    pub fn expr_text(&self, expr: &Expr) -> Option<&str> {
+        self.slice(expr.span)
+    }
+
+    /// Line `line` of the source, counting from 1, without its line break.
+    pub fn line(&self, line: u32) -> Option<&str> {
+        self.text.lines().nth(line.checked_sub(1)? as usize)
+    }
+}
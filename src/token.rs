@@ -1,5 +1,9 @@
+use crate::operators::Fixity;
 use lazy_static::lazy_static;
+use num_bigint::BigInt;
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Loc {
@@ -13,10 +17,42 @@ impl Loc {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Assoc {
-    Left,
-    Right,
+/// A stretch of source text, as a byte range for slicing the source and as
+/// line/col locations for people. The end is exclusive in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub start_loc: Loc,
+    pub end_loc: Loc,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, start_loc: Loc, end_loc: Loc) -> Self {
+        Self {
+            start,
+            end,
+            start_loc,
+            end_loc,
+        }
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start, other.end, self.start_loc, other.end_loc)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -24,9 +60,25 @@ pub enum TokenValue {
     // Literals
     Identifier(String),
     Integer(i64),
+    // An integer literal too large for `i64`
+    BigInteger(BigInt),
     Float(f64),
     String(String),
 
+    // A function whose argument list has been closed, with its argument count
+    Call(String, usize),
+    // A binding on the left side of an assignment
+    Target(String),
+    // The name after `.`, and a method called through it with the receiver
+    // counted among its arguments
+    Field(String),
+    Method(String, usize),
+    // A subscript `[...]` whose brackets have been closed, with its operand count
+    Index(usize),
+    // A symbol declared as an operator at runtime, as lexed and once placed
+    Operator(String),
+    CustomOp(String, Fixity),
+
     // Keywords
     Struct,
     Enum,
@@ -52,10 +104,13 @@ pub enum TokenValue {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Colon,
     ColonColon,
+    Question,
     Semicolon,
 
     // Arithmetic Operators
@@ -64,6 +119,20 @@ pub enum TokenValue {
     Star,
     Slash,
     Mod,
+    StarStar,
+    PlusPlus,
+    MinusMinus,
+    Quote,
+    UnaryPlus,
+    UnaryMinus,
+    Factorial,
+    // `√`, read with math glyphs
+    Root,
+    PostIncrement,
+    PostDecrement,
+    Prime,
+    // A `?` whose `:` has been found
+    Ternary,
 
     // Boolean/Logic Operators
     LogNot,
@@ -83,6 +152,7 @@ pub enum TokenValue {
     StarEqual,
     SlashEqual,
     ModEqual,
+    StarStarEqual,
     BitAndEqual,
     BitOrEqual,
     BitXorEqual,
@@ -101,141 +171,191 @@ pub enum TokenValue {
 }
 
 impl TokenValue {
-    pub fn precedence(&self) -> Option<u8> {
-        match self {
-            Self::Comma => Some(0),
+    pub fn is_assignment(&self) -> bool {
+        matches!(
+            self,
             Self::Equal
-            | Self::PlusEqual
-            | Self::MinusEqual
-            | Self::StarEqual
-            | Self::SlashEqual
-            | Self::ModEqual
-            | Self::RightShiftEqual
-            | Self::LeftShiftEqual
-            | Self::BitAndEqual
-            | Self::BitOrEqual
-            | Self::BitXorEqual => Some(1),
-            Self::LogOr => Some(3),
-            Self::LogAnd => Some(4),
-            Self::BitOr => Some(5),
-            Self::BitXor => Some(6),
-            Self::BitAnd => Some(7),
-            Self::EqualEqual | Self::NotEqual => Some(8),
-            Self::Less | Self::LessEqual | Self::Greater | Self::GreaterEqual => Some(9),
-            Self::LeftShift | Self::RightShift => Some(10),
-            Self::Plus | Self::Minus => Some(11),
-            Self::Star | Self::Slash | Self::Mod => Some(12),
-            Self::LogNot | Self::BitNot => Some(13),
-            _ => None,
-        }
+                | Self::PlusEqual
+                | Self::MinusEqual
+                | Self::StarEqual
+                | Self::SlashEqual
+                | Self::ModEqual
+                | Self::StarStarEqual
+                | Self::BitAndEqual
+                | Self::BitOrEqual
+                | Self::BitXorEqual
+                | Self::LeftShiftEqual
+                | Self::RightShiftEqual
+        )
     }
 
-    pub fn assoc(&self) -> Option<Assoc> {
-        match self {
-            Self::Equal
-            | Self::PlusEqual
-            | Self::MinusEqual
-            | Self::StarEqual
-            | Self::SlashEqual
-            | Self::ModEqual
-            | Self::RightShiftEqual
-            | Self::LeftShiftEqual
-            | Self::BitAndEqual
-            | Self::BitOrEqual
-            | Self::BitXorEqual
-            | Self::LogNot
-            | Self::BitNot => Some(Assoc::Right),
-            Self::Comma
-            | Self::LogOr
-            | Self::LogAnd
-            | Self::BitOr
-            | Self::BitXor
-            | Self::BitAnd
-            | Self::EqualEqual
-            | Self::NotEqual
-            | Self::Less
-            | Self::LessEqual
-            | Self::Greater
-            | Self::GreaterEqual
-            | Self::LeftShift
-            | Self::RightShift
-            | Self::Plus
-            | Self::Minus
-            | Self::Star
-            | Self::Slash
-            | Self::Mod => Some(Assoc::Left),
-            _ => None,
-        }
+    pub fn is_prefix_op(&self) -> bool {
+        matches!(
+            self,
+            Self::LogNot
+                | Self::BitNot
+                | Self::UnaryPlus
+                | Self::UnaryMinus
+                | Self::Root
+                | Self::CustomOp(_, Fixity::Prefix)
+        )
     }
 
-    pub fn is_func(&self, known_funcs: &Vec<String>) -> bool {
-        match self {
-            Self::Identifier(str) if known_funcs.contains(&str) => true,
-            _ => false,
-        }
+    pub fn is_postfix_op(&self) -> bool {
+        matches!(
+            self,
+            Self::Factorial
+                | Self::PostIncrement
+                | Self::PostDecrement
+                | Self::Prime
+                | Self::CustomOp(_, Fixity::Postfix)
+        )
     }
 
-    pub fn is_op(&self) -> bool {
-        match self {
-            Self::LeftParen
-            | Self::Equal
-            | Self::PlusEqual
-            | Self::MinusEqual
-            | Self::StarEqual
-            | Self::SlashEqual
-            | Self::ModEqual
-            | Self::RightShiftEqual
-            | Self::LeftShiftEqual
-            | Self::BitAndEqual
-            | Self::BitOrEqual
-            | Self::BitXorEqual
-            | Self::LogNot
-            | Self::BitNot
-            // | Self::Comma
-            | Self::LogOr
-            | Self::LogAnd
-            | Self::BitOr
-            | Self::BitXor
-            | Self::BitAnd
-            | Self::EqualEqual
-            | Self::NotEqual
-            | Self::Less
-            | Self::LessEqual
-            | Self::Greater
-            | Self::GreaterEqual
-            | Self::LeftShift
-            | Self::RightShift
-            | Self::Plus
-            | Self::Minus
-            | Self::Star
-            | Self::Slash
-            | Self::Mod => true,
-            _ => false,
-        }
+    /// Postfix operators that write back to the binding they are applied to.
+    pub fn is_increment(&self) -> bool {
+        matches!(self, Self::PostIncrement | Self::PostDecrement)
     }
 
-    pub fn is_binding(&self, known_bindings: &Vec<String>) -> bool {
-        match self {
-            Self::Identifier(str) if known_bindings.contains(&str) => true,
-            _ => false,
-        }
+    /// Values written directly in the source, which are operands on their own.
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            Self::Integer(_)
+                | Self::BigInteger(_)
+                | Self::Float(_)
+                | Self::String(_)
+                | Self::True
+                | Self::False
+                | Self::Null
+        )
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        KEYWORD_TABLE.values().any(|keyword| keyword == self)
+    }
+}
+
+impl fmt::Display for TokenValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lexeme = match self {
+            Self::Identifier(name) => return write!(f, "{}", name),
+            Self::Call(name, arity) => return write!(f, "{}/{}", name, arity),
+            Self::Target(name) => return write!(f, "&{}", name),
+            Self::Field(name) => return write!(f, "{}", name),
+            Self::Method(name, arity) => return write!(f, ".{}/{}", name, arity),
+            Self::Index(arity) => return write!(f, "[]/{}", arity),
+            Self::Operator(symbol) | Self::CustomOp(symbol, _) => return write!(f, "{}", symbol),
+            Self::Integer(i) => return write!(f, "{}", i),
+            Self::BigInteger(i) => return write!(f, "{}", i),
+            Self::Float(x) => return write!(f, "{:?}", x),
+            Self::String(str) => return write!(f, "{:?}", str),
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Trait => "trait",
+            Self::Impl => "impl",
+            Self::Fn => "fn",
+            Self::Let => "let",
+            Self::Mut => "mut",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::While => "while",
+            Self::For => "for",
+            Self::In => "in",
+            Self::Return => "return",
+            Self::Yield => "yield",
+            Self::Break => "break",
+            Self::True => "true",
+            Self::False => "false",
+            Self::Null => "null",
+            Self::LeftParen => "(",
+            Self::RightParen => ")",
+            Self::LeftBrace => "{",
+            Self::RightBrace => "}",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Comma => ",",
+            Self::Dot => ".",
+            Self::Colon => ":",
+            Self::ColonColon => "::",
+            Self::Question => "?",
+            Self::Ternary => "?:",
+            Self::Semicolon => ";",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Star => "*",
+            Self::Slash => "/",
+            Self::Mod => "%",
+            Self::StarStar => "**",
+            Self::PlusPlus | Self::PostIncrement => "++",
+            Self::MinusMinus | Self::PostDecrement => "--",
+            Self::Quote | Self::Prime => "'",
+            Self::Factorial => "!",
+            Self::Root => "√",
+            Self::UnaryPlus => "u+",
+            Self::UnaryMinus => "u-",
+            Self::LogNot => "!",
+            Self::BitNot => "~",
+            Self::LogAnd => "&&",
+            Self::BitAnd => "&",
+            Self::LogOr => "||",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::LeftShift => "<<",
+            Self::RightShift => ">>",
+            Self::Equal => "=",
+            Self::PlusEqual => "+=",
+            Self::MinusEqual => "-=",
+            Self::StarEqual => "*=",
+            Self::SlashEqual => "/=",
+            Self::ModEqual => "%=",
+            Self::StarStarEqual => "**=",
+            Self::BitAndEqual => "&=",
+            Self::BitOrEqual => "|=",
+            Self::BitXorEqual => "^=",
+            Self::LeftShiftEqual => "<<=",
+            Self::RightShiftEqual => ">>=",
+            Self::EqualEqual => "==",
+            Self::NotEqual => "!=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::EOF => "<eof>",
+        };
+        write!(f, "{}", lexeme)
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
-    start_loc: Loc,
-    end_loc: Loc,
+    span: Span,
     pub value: TokenValue,
 }
 
 impl Token {
-    pub fn new(start_loc: Loc, end_loc: Loc, value: TokenValue) -> Self {
-        Self {
-            start_loc,
-            end_loc,
-            value,
-        }
+    pub fn new(span: Span, value: TokenValue) -> Self {
+        Self { span, value }
+    }
+
+    /// Where the token was lexed. Tokens the parser joins or rewrites keep the
+    /// span of the text they came from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn start_loc(&self) -> Loc {
+        self.span.start_loc
+    }
+
+    pub fn end_loc(&self) -> Loc {
+        self.span.end_loc
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
     }
 }
 
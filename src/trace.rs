@@ -0,0 +1,309 @@
+use crate::parser::ParseError;
+use crate::token::Token;
+use std::collections::VecDeque;
+
+/// Where a token lives at a given point of the algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    InputQueue,
+    OperatorStack,
+    OutputQueue,
+    Discarded,
+    Rejected,
+}
+
+/// The rule of the shunting-yard algorithm that caused a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    JoinPath,
+    SplitOperator,
+    Operand,
+    Function,
+    Operator,
+    PrefixOperator,
+    PostfixOperator,
+    PopHigherPrecedence,
+    BindTarget,
+    Comma,
+    PopUntilComma,
+    LeftParen,
+    RightParen,
+    PopUntilLeftParen,
+    DiscardLeftParen,
+    Member,
+    PopMember,
+    Method,
+    LeftBracket,
+    RightBracket,
+    PopUntilLeftBracket,
+    PopIndex,
+    Branch,
+    Question,
+    PopUntilQuestion,
+    Colon,
+    PopFunction,
+    Drain,
+    Fail,
+}
+
+impl Rule {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::JoinPath => "'::' joins the names around it into one qualified name",
+            Self::SplitOperator => {
+                "no operator reads the pair as one here, split it into two single operators"
+            }
+            Self::Operand => "operand goes straight to the output queue",
+            Self::Function => "function is pushed onto the operator stack",
+            Self::Operator => "operator is pushed onto the operator stack",
+            Self::PrefixOperator => {
+                "prefix operator has no left operand, push it without popping anything"
+            }
+            Self::PostfixOperator => {
+                "postfix operator applies to the operand already output, it goes straight after it"
+            }
+            Self::PopHigherPrecedence => {
+                "operator on the stack binds tighter, pop it to the output queue"
+            }
+            Self::BindTarget => {
+                "binding before an assignment is written to, not read, mark it as the target"
+            }
+            Self::Comma => "comma starts the next argument, discard it",
+            Self::PopUntilComma => "pop operators back to the enclosing '('",
+            Self::LeftParen => "'(' is pushed onto the operator stack",
+            Self::RightParen => "')' is discarded",
+            Self::PopUntilLeftParen => "pop operators until the matching '('",
+            Self::DiscardLeftParen => "matching '(' is popped and discarded",
+            Self::Member => "name after '.' is a member, it goes straight to the output queue",
+            Self::PopMember => {
+                "'.' has both operands, pop it at once so nothing else takes the member name"
+            }
+            Self::Method => {
+                "'.' before a call is dropped, the receiver becomes the method's first argument"
+            }
+            Self::LeftBracket => {
+                "'[' after an operand starts a subscript, push it like a function and its '('"
+            }
+            Self::RightBracket => "']' is discarded",
+            Self::PopUntilLeftBracket => "pop operators until the matching '['",
+            Self::PopIndex => {
+                "'[' goes to the output queue as the index operator with its operand count"
+            }
+            Self::Branch => {
+                "'?' goes to the output queue, where it skips the first branch if the condition is false"
+            }
+            Self::Question => {
+                "'?' is also pushed onto the operator stack, where it waits like '(' for its ':'"
+            }
+            Self::PopUntilQuestion => "pop operators back to the matching '?'",
+            Self::Colon => {
+                "':' goes to the output queue to skip the second branch, its '?' becomes the ternary operator"
+            }
+            Self::PopFunction => {
+                "function owning the parentheses goes to the output queue with its argument count"
+            }
+            Self::Drain => "input is empty, pop remaining operators to the output queue",
+            Self::Fail => "token cannot be placed, parsing stops",
+        }
+    }
+}
+
+/// Contents of the three containers, with the operator stack top first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct State {
+    pub input_queue: VecDeque<Token>,
+    pub operator_stack: VecDeque<Token>,
+    pub output_queue: VecDeque<Token>,
+}
+
+/// A single token moving between two places, and the state right after the move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub rule: Rule,
+    pub token: Token,
+    pub from: Place,
+    pub to: Place,
+    pub state: State,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    initial: State,
+    steps: Vec<Step>,
+    error: Option<ParseError>,
+}
+
+impl Trace {
+    pub fn new(initial: State) -> Self {
+        Self {
+            initial,
+            steps: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// Marks the trace as ending in `error`; the last step is the rejected token.
+    pub fn fail(&mut self, error: ParseError) {
+        self.error = Some(error);
+    }
+
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    pub fn initial(&self) -> &State {
+        &self.initial
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// State after `n` steps have been applied; `0` is the initial state.
+    pub fn state_at(&self, n: usize) -> &State {
+        match n {
+            0 => &self.initial,
+            n => &self.steps[n.min(self.steps.len()) - 1].state,
+        }
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, Self> {
+        Cursor::new(self)
+    }
+}
+
+impl Replay for Trace {
+    type State = State;
+    type Step = Step;
+
+    fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    fn state_at(&self, n: usize) -> &State {
+        self.state_at(n)
+    }
+
+    fn step(&self, n: usize) -> Option<&Step> {
+        self.steps.get(n)
+    }
+}
+
+/// A recorded run that can be stepped through, such as a parse [`Trace`] or an
+/// evaluation trace.
+pub trait Replay {
+    type State;
+    type Step;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// State after `n` steps have been applied; `0` is the initial state.
+    fn state_at(&self, n: usize) -> &Self::State;
+
+    /// The `n`th step, counting from zero.
+    fn step(&self, n: usize) -> Option<&Self::Step>;
+}
+
+/// Replays a recorded run forward and backward.
+#[derive(Debug)]
+pub struct Cursor<'a, T> {
+    trace: &'a T,
+    pos: usize,
+}
+
+impl<T> Clone for Cursor<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Cursor<'_, T> {}
+
+impl<'a, T: Replay> Cursor<'a, T> {
+    pub fn new(trace: &'a T) -> Self {
+        Self { trace, pos: 0 }
+    }
+
+    pub fn trace(&self) -> &'a T {
+        self.trace
+    }
+
+    /// Number of steps applied so far.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn len(&self) -> usize {
+        self.trace.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trace.is_empty()
+    }
+
+    pub fn state(&self) -> &'a T::State {
+        self.trace.state_at(self.pos)
+    }
+
+    /// The step that produced the current state, if any.
+    pub fn step(&self) -> Option<&'a T::Step> {
+        match self.pos {
+            0 => None,
+            n => self.trace.step(n - 1),
+        }
+    }
+
+    pub fn at_start(&self) -> bool {
+        self.pos == 0
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos == self.trace.len()
+    }
+
+    pub fn forward(&mut self) -> bool {
+        if self.at_end() {
+            false
+        } else {
+            self.pos += 1;
+            true
+        }
+    }
+
+    pub fn back(&mut self) -> bool {
+        if self.at_start() {
+            false
+        } else {
+            self.pos -= 1;
+            true
+        }
+    }
+
+    pub fn to_start(&mut self) {
+        self.pos = 0;
+    }
+
+    pub fn to_end(&mut self) {
+        self.pos = self.trace.len();
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos.min(self.trace.len());
+    }
+}
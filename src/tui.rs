@@ -0,0 +1,522 @@
+use crate::eval::{EvalTrace, Operand};
+use crate::token::{Token, TokenValue};
+use crate::trace::{Cursor, Place, Trace};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Auto-play speeds in steps per second.
+const SPEEDS: [f64; 7] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+
+const HELP: &str = "←/h back  →/l forward  Home/g start  End/G end  space play/pause  +/- speed  tab parse/eval  PgUp/PgDn or [/] statement  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Parse,
+    Eval,
+}
+
+/// The parse of one statement and, optionally, the evaluation of its output.
+struct Statement<'a> {
+    parse: Cursor<'a, Trace>,
+    eval: Option<Cursor<'a, EvalTrace>>,
+}
+
+/// Full-screen stepper over the statements of a program, one at a time.
+pub struct Stepper<'a> {
+    source: &'a str,
+    statements: Vec<Statement<'a>>,
+    current: usize,
+    view: View,
+    playing: bool,
+    speed: usize,
+}
+
+/// Puts the terminal in raw mode on the alternate screen and restores it on drop,
+/// so a panic or early return does not leave the user's shell unusable.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl<'a> Stepper<'a> {
+    pub fn new(source: &'a str, trace: &'a Trace) -> Self {
+        Self {
+            source,
+            statements: vec![Statement {
+                parse: trace.cursor(),
+                eval: None,
+            }],
+            current: 0,
+            view: View::Parse,
+            playing: false,
+            speed: 2,
+        }
+    }
+
+    /// Adds the parse of a further statement after the ones already given.
+    pub fn with_statement(mut self, trace: &'a Trace) -> Self {
+        self.statements.push(Statement {
+            parse: trace.cursor(),
+            eval: None,
+        });
+        self
+    }
+
+    /// Lets the user continue from the parse of the last statement given into the
+    /// evaluation of its RPN.
+    pub fn with_evaluation(mut self, trace: &'a EvalTrace) -> Self {
+        if let Some(statement) = self.statements.last_mut() {
+            statement.eval = Some(trace.cursor());
+        }
+        self
+    }
+
+    fn statement(&self) -> &Statement<'a> {
+        &self.statements[self.current]
+    }
+
+    fn statement_mut(&mut self) -> &mut Statement<'a> {
+        &mut self.statements[self.current]
+    }
+
+    /// Moves to another statement, starting over at its parse. Returns `false`
+    /// when there is no such statement.
+    fn select(&mut self, index: Option<usize>) -> bool {
+        match index {
+            Some(index) if index < self.statements.len() => {
+                self.current = index;
+                self.view = View::Parse;
+                self.jump_to_start();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn run(mut self) -> io::Result<()> {
+        let _guard = TerminalGuard::enter()?;
+        let mut stdout = io::stdout();
+        let mut last_tick = Instant::now();
+
+        loop {
+            self.draw(&mut stdout)?;
+
+            let interval = Duration::from_secs_f64(1.0 / SPEEDS[self.speed]);
+            let timeout = if self.playing {
+                interval.saturating_sub(last_tick.elapsed())
+            } else {
+                Duration::from_secs(60)
+            };
+
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if !self.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if self.playing && last_tick.elapsed() >= interval {
+                if !self.forward() {
+                    if self.view == View::Parse && self.statement().eval.is_some() {
+                        self.view = View::Eval;
+                        self.jump_to_start();
+                    } else if !self.select(Some(self.current + 1)) {
+                        self.playing = false;
+                    }
+                }
+                last_tick = Instant::now();
+            }
+        }
+    }
+
+    /// Returns `false` when the user asked to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.kind == KeyEventKind::Release {
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => {
+                self.playing = false;
+                self.forward();
+            }
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
+                self.playing = false;
+                self.back();
+            }
+            KeyCode::Home | KeyCode::Char('g') => {
+                self.playing = false;
+                self.jump_to_start();
+            }
+            KeyCode::End | KeyCode::Char('G') => {
+                self.playing = false;
+                self.jump_to_end();
+            }
+            KeyCode::PageDown | KeyCode::Char(']') => {
+                self.playing = false;
+                self.select(Some(self.current + 1));
+            }
+            KeyCode::PageUp | KeyCode::Char('[') => {
+                self.playing = false;
+                self.select(self.current.checked_sub(1));
+            }
+            KeyCode::Tab if self.statement().eval.is_some() => {
+                self.playing = false;
+                self.view = match self.view {
+                    View::Parse => View::Eval,
+                    View::Eval => View::Parse,
+                };
+            }
+            KeyCode::Char(' ') => {
+                if self.at_end() {
+                    self.jump_to_start();
+                }
+                self.playing = !self.playing;
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+            }
+            KeyCode::Char('-') => {
+                self.speed = self.speed.saturating_sub(1);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn forward(&mut self) -> bool {
+        let view = self.view;
+        let statement = self.statement_mut();
+        match (view, &mut statement.eval) {
+            (View::Eval, Some(eval)) => eval.forward(),
+            _ => statement.parse.forward(),
+        }
+    }
+
+    fn back(&mut self) -> bool {
+        let view = self.view;
+        let statement = self.statement_mut();
+        match (view, &mut statement.eval) {
+            (View::Eval, Some(eval)) => eval.back(),
+            _ => statement.parse.back(),
+        }
+    }
+
+    fn jump_to_start(&mut self) {
+        let view = self.view;
+        let statement = self.statement_mut();
+        match (view, &mut statement.eval) {
+            (View::Eval, Some(eval)) => eval.to_start(),
+            _ => statement.parse.to_start(),
+        }
+    }
+
+    fn jump_to_end(&mut self) {
+        let view = self.view;
+        let statement = self.statement_mut();
+        match (view, &mut statement.eval) {
+            (View::Eval, Some(eval)) => eval.to_end(),
+            _ => statement.parse.to_end(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        let statement = self.statement();
+        match (self.view, &statement.eval) {
+            (View::Eval, Some(eval)) => eval.at_end(),
+            _ => statement.parse.at_end(),
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (width, _) = terminal::size()?;
+        let width = width as usize;
+
+        let statement = self.statement();
+        let (phase, pos, len) = match (self.view, &statement.eval) {
+            (View::Eval, Some(eval)) => ("evaluating", eval.pos(), eval.len()),
+            _ => ("parsing", statement.parse.pos(), statement.parse.len()),
+        };
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        queue!(
+            out,
+            SetAttribute(Attribute::Bold),
+            Print("Shunting-yard visualizer"),
+            SetAttribute(Attribute::Reset),
+            Print(format!(
+                " — statement {}/{}   {}   step {}/{}   {} {} steps/s",
+                self.current + 1,
+                self.statements.len(),
+                phase,
+                pos,
+                len,
+                if self.playing { "▶" } else { "⏸" },
+                SPEEDS[self.speed]
+            )),
+        )?;
+
+        let frame = match (self.view, &statement.eval) {
+            (View::Eval, Some(eval)) => eval_frame(eval),
+            _ => parse_frame(&statement.parse),
+        };
+
+        let mut row = 2;
+        for (line_no, line) in self.source.lines().enumerate() {
+            queue!(out, MoveTo(2, row))?;
+            let line_no = line_no as u32 + 1;
+            for (col, c) in line.chars().enumerate() {
+                let col = col as u32 + 1;
+                if frame.current.is_some_and(|t| covers(t, line_no, col)) {
+                    queue!(
+                        out,
+                        SetForegroundColor(Color::Black),
+                        SetAttribute(Attribute::Reverse),
+                        Print(c),
+                        SetAttribute(Attribute::Reset),
+                        ResetColor
+                    )?;
+                } else {
+                    queue!(out, Print(c))?;
+                }
+            }
+            row += 1;
+        }
+
+        row += 1;
+        queue!(
+            out,
+            MoveTo(2, row),
+            Print(truncate(&frame.description, width.saturating_sub(2)))
+        )?;
+        row += 1;
+        if let Some(error) = frame.error {
+            queue!(
+                out,
+                MoveTo(2, row),
+                SetForegroundColor(Color::Red),
+                Print(truncate(
+                    &format!("error: {}", error),
+                    width.saturating_sub(2)
+                )),
+                ResetColor
+            )?;
+        }
+        row += 1;
+
+        for panel in &frame.panels {
+            row = draw_panel(out, row, width, panel)?;
+        }
+
+        queue!(
+            out,
+            MoveTo(0, row + 1),
+            SetForegroundColor(Color::DarkGrey),
+            Print(truncate(HELP, width)),
+            ResetColor
+        )?;
+        out.flush()
+    }
+}
+
+/// A titled row of items, one of which may be highlighted as just moved.
+struct Panel {
+    title: &'static str,
+    items: Vec<String>,
+    highlight: Option<usize>,
+}
+
+/// Everything drawn for the step under the cursor.
+struct Frame<'a> {
+    current: Option<&'a Token>,
+    description: String,
+    error: Option<String>,
+    panels: Vec<Panel>,
+}
+
+fn parse_frame<'a>(cursor: &Cursor<'a, Trace>) -> Frame<'a> {
+    let step = cursor.step();
+    let state = cursor.state();
+    let description = match step {
+        Some(step) => format!(
+            "{}: {} ({} → {})",
+            step.token,
+            step.rule.describe(),
+            place_name(step.from).to_lowercase(),
+            place_name(step.to).to_lowercase()
+        ),
+        None => "press → to start".to_string(),
+    };
+
+    let panel = |place: Place, tokens: Vec<&Token>| {
+        let highlight = step
+            .filter(|s| s.to == place)
+            .and_then(|s| tokens.iter().rposition(|t| **t == s.token));
+        Panel {
+            title: match place {
+                Place::OperatorStack => "Operator stack (top →)",
+                _ => place_name(place),
+            },
+            items: tokens.iter().map(|t| t.to_string()).collect(),
+            highlight,
+        }
+    };
+    Frame {
+        current: step.map(|s| &s.token),
+        description,
+        error: cursor
+            .trace()
+            .error()
+            .filter(|_| cursor.at_end())
+            .map(|e| e.to_string()),
+        panels: vec![
+            panel(Place::InputQueue, state.input_queue.iter().collect()),
+            panel(
+                Place::OperatorStack,
+                state.operator_stack.iter().rev().collect(),
+            ),
+            panel(Place::OutputQueue, state.output_queue.iter().collect()),
+        ],
+    }
+}
+
+fn eval_frame<'a>(cursor: &Cursor<'a, EvalTrace>) -> Frame<'a> {
+    let step = cursor.step();
+    let state = cursor.state();
+    let description = match step {
+        Some(step) => {
+            let operands: Vec<String> = step.operands.iter().map(|v| v.to_string()).collect();
+            let result = match (&step.token.value, &step.result, step.operands.as_slice()) {
+                (_, Some(value), _) => format!("push {}", value),
+                (TokenValue::Question, None, [Operand::Value(condition)]) => {
+                    match condition.truthy() {
+                        true => "take the first branch".to_string(),
+                        false => "skip to the second branch".to_string(),
+                    }
+                }
+                (TokenValue::Colon, None, []) => "skip the second branch".to_string(),
+                _ => "cannot be evaluated".to_string(),
+            };
+            if operands.is_empty() {
+                format!("{}: {}", step.token, result)
+            } else {
+                format!("{}: pop {} → {}", step.token, operands.join(", "), result)
+            }
+        }
+        None => "press → to start".to_string(),
+    };
+
+    Frame {
+        current: step.map(|s| &s.token),
+        description,
+        error: cursor
+            .trace()
+            .error()
+            .filter(|_| cursor.at_end())
+            .map(|e| e.to_string()),
+        panels: vec![
+            Panel {
+                title: "RPN input",
+                items: state.input_queue.iter().map(|t| t.to_string()).collect(),
+                highlight: None,
+            },
+            Panel {
+                title: "Value stack (top →)",
+                items: state.value_stack.iter().map(|v| v.to_string()).collect(),
+                highlight: step
+                    .filter(|s| s.result.is_some())
+                    .map(|_| state.value_stack.len() - 1),
+            },
+        ],
+    }
+}
+
+/// Draws a boxed, wrapped row of items and returns the next free row.
+fn draw_panel(out: &mut impl Write, row: u16, width: usize, panel: &Panel) -> io::Result<u16> {
+    let inner = width.saturating_sub(4).max(1);
+    let mut lines: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut used = 0;
+    for (i, item) in panel.items.iter().enumerate() {
+        let len = item.chars().count() + 1;
+        if used + len > inner && used > 0 {
+            lines.push(Vec::new());
+            used = 0;
+        }
+        lines.last_mut().unwrap().push(i);
+        used += len;
+    }
+
+    let header = format!("┌ {} ", panel.title);
+    let pad = width.saturating_sub(header.chars().count() + 1);
+    queue!(
+        out,
+        MoveTo(0, row),
+        Print(format!("{}{}┐", header, "─".repeat(pad)))
+    )?;
+    let mut row = row + 1;
+    for line in lines {
+        queue!(out, MoveTo(0, row), Print("│ "))?;
+        for i in line {
+            if panel.highlight == Some(i) {
+                queue!(
+                    out,
+                    SetForegroundColor(Color::Yellow),
+                    SetAttribute(Attribute::Bold),
+                    Print(&panel.items[i]),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor,
+                    Print(" ")
+                )?;
+            } else {
+                queue!(out, Print(format!("{} ", panel.items[i])))?;
+            }
+        }
+        queue!(out, MoveTo(width.saturating_sub(1) as u16, row), Print("│"))?;
+        row += 1;
+    }
+    queue!(
+        out,
+        MoveTo(0, row),
+        Print(format!("└{}┘", "─".repeat(width.saturating_sub(2))))
+    )?;
+    Ok(row + 1)
+}
+
+fn covers(t: &Token, line: u32, col: u32) -> bool {
+    let (start, end) = (t.start_loc(), t.end_loc());
+    let after_start = line > start.line || (line == start.line && col >= start.col);
+    let before_end = line < end.line || (line == end.line && col < end.col);
+    after_start && before_end
+}
+
+fn place_name(place: Place) -> &'static str {
+    match place {
+        Place::InputQueue => "Input queue",
+        Place::OperatorStack => "Operator stack",
+        Place::OutputQueue => "Output queue",
+        Place::Discarded => "Discarded",
+        Place::Rejected => "Rejected",
+    }
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}